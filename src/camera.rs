@@ -0,0 +1,175 @@
+use super::{Map, Position, QueuedMovement, Renderable, TileType};
+use rltk::{Point, Rltk, RGB};
+use specs::prelude::*;
+use std::cmp::{max, min};
+
+/// Width of the map viewport on screen, in console cells
+pub const VIEW_WIDTH: i32 = 80;
+/// Height of the map viewport on screen. Rows below it are left free for UI panels.
+pub const VIEW_HEIGHT: i32 = 43;
+
+/// The window of world coordinates currently drawn to the screen
+pub struct Viewport {
+    /// world coordinates shown in the top-left cell of the screen
+    pub origin: Point,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Viewport {
+    /// Convert a world coordinate to a screen cell, or None when it falls outside the viewport
+    pub fn world_to_screen(&self, world: Point) -> Option<Point> {
+        let screen = Point::new(world.x - self.origin.x, world.y - self.origin.y);
+        if screen.x < 0 || screen.x >= self.width || screen.y < 0 || screen.y >= self.height {
+            return None;
+        }
+        Some(screen)
+    }
+
+    /// Convert a screen cell (e.g. the mouse position) back into world coordinates
+    pub fn screen_to_world(&self, screen: Point) -> Point {
+        Point::new(screen.x + self.origin.x, screen.y + self.origin.y)
+    }
+}
+
+/// Pick the first world coordinate on one axis so the player stays centred,
+/// without scrolling past the edges of a map that is larger than the view
+fn axis_origin(player: i32, view: i32, map: i32) -> i32 {
+    if map <= view {
+        // small maps sit in the middle of the viewport
+        (map - view) / 2
+    } else {
+        (player - view / 2).clamp(0, map - view)
+    }
+}
+
+/// Work out the viewport centred on the player's `Point` resource
+pub fn get_viewport(ecs: &World) -> Viewport {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+
+    Viewport {
+        origin: Point::new(
+            axis_origin(player_pos.x, VIEW_WIDTH, map.width),
+            axis_origin(player_pos.y, VIEW_HEIGHT, map.height),
+        ),
+        width: VIEW_WIDTH,
+        height: VIEW_HEIGHT,
+    }
+}
+
+/// Draw the part of the map and its entities that falls inside the viewport
+pub fn render_camera(ecs: &World, ctx: &mut Rltk) {
+    let map = ecs.fetch::<Map>();
+    let viewport = get_viewport(ecs);
+
+    // draw both visible and revealed tiles on the map
+    for screen_y in 0..viewport.height {
+        for screen_x in 0..viewport.width {
+            let world = viewport.screen_to_world(Point::new(screen_x, screen_y));
+            if world.x < 0 || world.x >= map.width || world.y < 0 || world.y >= map.height {
+                continue;
+            }
+
+            let idx = map.xy_idx(world.x, world.y);
+            if map.revealed_tiles[idx] {
+                let (glyph, fg, bg) = get_tile_glyph(idx, &map);
+                ctx.set(screen_x, screen_y, fg, bg, glyph);
+            }
+        }
+    }
+
+    // highlight the rest of any walk the player has queued up
+    let player_entity = ecs.fetch::<Entity>();
+    if let Some(queue) = ecs.read_storage::<QueuedMovement>().get(*player_entity) {
+        for idx in queue.path.iter() {
+            let (x, y) = map.idx_xy(*idx);
+            if let Some(screen) = viewport.world_to_screen(Point::new(x, y)) {
+                ctx.set_bg(screen.x, screen.y, RGB::named(rltk::DARKSLATEBLUE));
+            }
+        }
+    }
+
+    // draw all other renderables that are within the vec of visible tiles on the map
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let mut data = (&positions, &renderables).join().collect::<Vec<_>>();
+    data.sort_by_key(|&(_pos, render)| std::cmp::Reverse(render.render_order));
+    for (pos, render) in data.iter() {
+        let idx = map.xy_idx(pos.x, pos.y);
+        if !map.visible_tiles[idx] {
+            continue;
+        }
+        if let Some(screen) = viewport.world_to_screen(Point::new(pos.x, pos.y)) {
+            ctx.set(screen.x, screen.y, render.fg, render.bg, render.glyph);
+        }
+    }
+}
+
+/// Draw a map that isn't the current level (e.g. a generation snapshot) whole, shrunk to fit
+/// and centred in the viewport. Each screen cell stands for a square block of tiles and shows
+/// the most notable one: stairs over floor over wall.
+pub fn render_map_snapshot(snapshot: &Map, ctx: &mut Rltk) {
+    let scale = max(
+        div_ceil(snapshot.width, VIEW_WIDTH),
+        div_ceil(snapshot.height, VIEW_HEIGHT),
+    )
+    .max(1);
+    let offset_x = (VIEW_WIDTH - div_ceil(snapshot.width, scale)) / 2;
+    let offset_y = (VIEW_HEIGHT - div_ceil(snapshot.height, scale)) / 2;
+
+    for screen_y in 0..div_ceil(snapshot.height, scale) {
+        for screen_x in 0..div_ceil(snapshot.width, scale) {
+            let block = (screen_y * scale..min((screen_y + 1) * scale, snapshot.height))
+                .flat_map(|y| {
+                    (screen_x * scale..min((screen_x + 1) * scale, snapshot.width))
+                        .map(move |x| (x, y))
+                })
+                .map(|(x, y)| snapshot.xy_idx(x, y))
+                .filter(|idx| snapshot.revealed_tiles[*idx])
+                .max_by_key(|idx| match snapshot.tiles[*idx] {
+                    TileType::Wall => 0,
+                    TileType::Floor => 1,
+                    TileType::DownStairs | TileType::UpStairs => 2,
+                });
+            if let Some(idx) = block {
+                let (glyph, fg, bg) = get_tile_glyph(idx, snapshot);
+                ctx.set(screen_x + offset_x, screen_y + offset_y, fg, bg, glyph);
+            }
+        }
+    }
+}
+
+fn div_ceil(value: i32, divisor: i32) -> i32 {
+    (value + divisor - 1) / divisor
+}
+
+/// Map the tile at idx to a renderable representation, greyed out when it is remembered but not in view
+fn get_tile_glyph(idx: usize, map: &Map) -> (rltk::FontCharType, RGB, RGB) {
+    let glyph;
+    let mut fg;
+    match map.tiles[idx] {
+        TileType::Floor => {
+            fg = RGB::from_f32(0.0, 0.5, 0.5);
+            glyph = rltk::to_cp437('.');
+        }
+        TileType::Wall => {
+            fg = RGB::from_f32(0.0, 1.0, 0.0);
+            glyph = rltk::to_cp437('#');
+        }
+        TileType::DownStairs => {
+            fg = RGB::from_f32(0.0, 1.0, 1.0);
+            glyph = rltk::to_cp437('>');
+        }
+        TileType::UpStairs => {
+            fg = RGB::from_f32(0.0, 1.0, 1.0);
+            glyph = rltk::to_cp437('<');
+        }
+    }
+
+    if !map.visible_tiles[idx] {
+        fg = fg.to_greyscale();
+    }
+
+    (glyph, fg, RGB::from_f32(0., 0., 0.))
+}
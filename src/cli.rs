@@ -0,0 +1,146 @@
+use super::map_builders::BUILDER_NAMES;
+use super::CornerCutting;
+
+/// How `--generate` writes out the levels it builds
+#[derive(PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    Ascii,
+    Json,
+}
+
+/// Options parsed from the command line
+pub struct CliOptions {
+    pub seed: Option<u64>,
+    /// one of `BUILDER_NAMES`
+    pub builder: Option<String>,
+    /// print levels to stdout instead of opening the game window
+    pub generate: bool,
+    pub format: OutputFormat,
+    /// how many consecutive seeds to generate
+    pub count: u64,
+    /// seeds only reproduce the game's own levels at depth 1
+    pub depth: i32,
+    pub corner_cutting: CornerCutting,
+}
+
+/// Parse the process arguments (without the executable name).
+/// Accepts both `--seed 42` and `--seed=42`, and likewise for every option that takes a value.
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<CliOptions, String> {
+    let mut options = CliOptions {
+        seed: None,
+        builder: None,
+        generate: false,
+        format: OutputFormat::Ascii,
+        count: 1,
+        depth: 1,
+        corner_cutting: CornerCutting::default(),
+    };
+    let mut args = args.peekable();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+
+        match flag.as_str() {
+            "--seed" => {
+                let value = match inline_value.or_else(|| args.next()) {
+                    Some(value) => value,
+                    None => return Err("--seed requires a value".to_string()),
+                };
+                let seed = value.parse::<u64>().map_err(|_| {
+                    format!("invalid seed '{}', expected an unsigned integer", value)
+                })?;
+                options.seed = Some(seed);
+            }
+            "--builder" => {
+                let value = match inline_value.or_else(|| args.next()) {
+                    Some(value) => value,
+                    None => return Err("--builder requires a value".to_string()),
+                };
+                if !BUILDER_NAMES.contains(&value.as_str()) {
+                    return Err(format!(
+                        "unknown builder '{}', expected one of: {}",
+                        value,
+                        BUILDER_NAMES.join(", ")
+                    ));
+                }
+                options.builder = Some(value);
+            }
+            "--corner-cutting" => {
+                let value = match inline_value.or_else(|| args.next()) {
+                    Some(value) => value,
+                    None => return Err("--corner-cutting requires a value".to_string()),
+                };
+                options.corner_cutting = match value.as_str() {
+                    "allowed" => CornerCutting::Allowed,
+                    "no-squeezing" => CornerCutting::NoSqueezing,
+                    "forbidden" => CornerCutting::Forbidden,
+                    _ => {
+                        return Err(format!(
+                            "unknown corner cutting rule '{}', expected allowed, no-squeezing or forbidden",
+                            value
+                        ))
+                    }
+                };
+            }
+            "--generate" => options.generate = true,
+            "--format" => {
+                let value = match inline_value.or_else(|| args.next()) {
+                    Some(value) => value,
+                    None => return Err("--format requires a value".to_string()),
+                };
+                options.format = match value.as_str() {
+                    "ascii" => OutputFormat::Ascii,
+                    "json" => OutputFormat::Json,
+                    _ => {
+                        return Err(format!(
+                            "unknown format '{}', expected ascii or json",
+                            value
+                        ))
+                    }
+                };
+            }
+            "--count" => {
+                let value = match inline_value.or_else(|| args.next()) {
+                    Some(value) => value,
+                    None => return Err("--count requires a value".to_string()),
+                };
+                options.count = match value.parse::<u64>() {
+                    Ok(count) if count > 0 => count,
+                    _ => {
+                        return Err(format!(
+                            "invalid count '{}', expected a positive integer",
+                            value
+                        ))
+                    }
+                };
+            }
+            "--depth" => {
+                let value = match inline_value.or_else(|| args.next()) {
+                    Some(value) => value,
+                    None => return Err("--depth requires a value".to_string()),
+                };
+                options.depth = match value.parse::<i32>() {
+                    Ok(depth) if depth > 0 => depth,
+                    _ => {
+                        return Err(format!(
+                            "invalid depth '{}', expected a positive integer",
+                            value
+                        ))
+                    }
+                };
+            }
+            _ => return Err(format!("unrecognised argument '{}'", flag)),
+        }
+    }
+
+    if !options.generate
+        && (options.format != OutputFormat::Ascii || options.count != 1 || options.depth != 1)
+    {
+        return Err("--format, --count and --depth only apply with --generate".to_string());
+    }
+
+    Ok(options)
+}
@@ -1,34 +1,189 @@
-use rltk::RGB;
+use rltk::{Point, RGB};
+use serde::{Deserialize, Serialize};
+use specs::error::NoError;
 use specs::prelude::*;
-use specs_derive::Component;
+use specs::saveload::{ConvertSaveload, Marker};
+use specs_derive::{Component, ConvertSaveload};
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
 }
 
-#[derive(Component)]
+/// Where an entity waits on a level the player isn't currently on
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct OtherLevelPosition {
+    pub x: i32,
+    pub y: i32,
+    pub depth: i32,
+}
+
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Renderable {
     pub glyph: rltk::FontCharType,
+    #[serde(with = "crate::saveload_system::rgb_hex")]
     pub fg: RGB,
+    #[serde(with = "crate::saveload_system::rgb_hex")]
     pub bg: RGB,
+    /// lower orders are drawn last, on top of anything sharing the tile
+    pub render_order: i32,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Viewshed {
     pub visible_tiles: Vec<rltk::Point>,
     pub range: i32,
     pub dirty: bool,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Monster {}
 
-#[derive(Component, Debug)]
+/// What a monster is doing, which decides how `MonsterAI` spends its turn
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AiState {
+    /// sees nothing and only hears sounds made close by
+    Asleep,
+    /// ambles around its home until it sees an enemy or hears something
+    Wandering,
+    /// heading for where an enemy was last seen, or a noise was heard
+    Chasing { x: i32, y: i32 },
+    /// frightened or badly hurt, so it keeps as far from its enemies as it can
+    Fleeing,
+    /// lost the trail and walking back to where it spawned
+    ReturningHome,
+}
+
+/// Which side an entity is on. The raws say how each faction reacts to the others.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Faction {
+    pub name: String,
+}
+
+/// Where a monster spawned. It wanders nearby and goes back there after losing its quarry.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Home {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Name {
     pub name: String,
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct BlocksTiles {}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct CombatStats {
+    pub max_hp: i32,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct WantsToMelee {
+    pub target: Entity,
+}
+
+#[derive(Component, Debug)]
+pub struct SufferDamage {
+    /// each queued hit with the name of whatever dealt it
+    pub amount: Vec<(i32, String)>,
+}
+
+impl SufferDamage {
+    /// Queue damage against a victim, stacking with any damage already queued this turn
+    pub fn new_damage<S: ToString>(
+        store: &mut WriteStorage<SufferDamage>,
+        victim: Entity,
+        amount: i32,
+        source: S,
+    ) {
+        if let Some(suffering) = store.get_mut(victim) {
+            suffering.amount.push((amount, source.to_string()));
+        } else {
+            let dmg = SufferDamage {
+                amount: vec![(amount, source.to_string())],
+            };
+            store.insert(victim, dmg).expect("Unable to insert damage");
+        }
+    }
+}
+
+/// Names whatever landed the killing blow on this entity
+#[derive(Component, Debug, Clone)]
+pub struct KilledBy {
+    pub name: String,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Item {}
+
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct InBackpack {
+    pub owner: Entity,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct WantsToPickupItem {
+    pub collected_by: Entity,
+    pub item: Entity,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct WantsToDropItem {
+    pub item: Entity,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Consumable {}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct ProvidesHealing {
+    pub heal_amount: i32,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct InflictsDamage {
+    pub damage: i32,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Ranged {
+    pub range: i32,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct AreaOfEffect {
+    pub radius: i32,
+}
+
+/// Status effect: the entity stumbles around and loses its turns until this runs out
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Confusion {
+    pub turns: i32,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct WantsToUseItem {
+    pub item: Entity,
+    /// the tile picked in targeting mode, None for items used on oneself
+    pub target: Option<Point>,
+}
+
+/// Marks entities that are written to the save file
+pub struct SerializeMe;
+
+/// Carries the world resources through the save file as a temporary entity
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct SerializationHelper {
+    pub map: super::map::Map,
+    pub dungeon_master: super::dungeon::MasterDungeonMap,
+    pub log: super::gamelog::GameLog,
+    pub rng: rltk::RandomNumberGenerator,
+    pub runstate: super::saveload_system::SavedRunState,
+}
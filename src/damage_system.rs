@@ -0,0 +1,73 @@
+use super::{CombatStats, GameLog, KilledBy, Name, Player, SufferDamage};
+use rltk::RGB;
+use specs::prelude::*;
+
+pub struct DamageSystem {}
+
+impl<'a> System<'a> for DamageSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, KilledBy>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut s_stats, mut s_damage, mut s_killed_by) = data;
+
+        for (entity, stats, damage) in (&entities, &mut s_stats, &s_damage).join() {
+            for (amount, source) in damage.amount.iter() {
+                let was_alive = stats.hp > 0;
+                stats.hp -= amount;
+                // remember the killing blow so death screens can name it
+                if was_alive && stats.hp <= 0 {
+                    s_killed_by
+                        .insert(
+                            entity,
+                            KilledBy {
+                                name: source.clone(),
+                            },
+                        )
+                        .expect("Unable to insert cause of death");
+                }
+            }
+        }
+
+        s_damage.clear();
+    }
+}
+
+/// Remove every entity whose hp has dropped to zero from the world.
+/// The player is never deleted; instead this returns true so the caller can end the run.
+pub fn delete_the_dead(ecs: &mut World) -> bool {
+    let mut dead: Vec<Entity> = Vec::new();
+    let mut player_died = false;
+    {
+        let combat_stats = ecs.read_storage::<CombatStats>();
+        let players = ecs.read_storage::<Player>();
+        let names = ecs.read_storage::<Name>();
+        let entities = ecs.entities();
+        let mut log = ecs.write_resource::<GameLog>();
+        for (entity, stats) in (&entities, &combat_stats).join() {
+            if stats.hp < 1 {
+                if players.get(entity).is_some() {
+                    player_died = true;
+                } else {
+                    if let Some(victim_name) = names.get(entity) {
+                        log.log(
+                            format!("{} is dead", &victim_name.name),
+                            RGB::named(rltk::ORANGE),
+                        );
+                    }
+                    dead.push(entity);
+                }
+            }
+        }
+    }
+
+    for victim in dead {
+        ecs.delete_entity(victim).expect("Unable to delete");
+    }
+
+    player_died
+}
@@ -0,0 +1,149 @@
+use super::{BlocksTiles, Map, OtherLevelPosition, Player, Position, Viewshed};
+use rltk::{BaseMap, Point};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Every level the player has left behind, keyed by depth
+#[derive(Default, Serialize, Deserialize, Clone)]
+pub struct MasterDungeonMap {
+    maps: HashMap<i32, Map>,
+}
+
+impl MasterDungeonMap {
+    pub fn new() -> MasterDungeonMap {
+        MasterDungeonMap {
+            maps: HashMap::new(),
+        }
+    }
+
+    pub fn store_map(&mut self, map: &Map) {
+        self.maps.insert(map.depth, map.clone());
+    }
+
+    /// A previously visited level, ready to be made current again
+    pub fn get_map(&self, depth: i32) -> Option<Map> {
+        let mut map = self.maps.get(&depth)?.clone();
+        // what the player could see when they left is no longer in view
+        map.visible_tiles = vec![false; (map.width * map.height) as usize];
+        map.tile_content = vec![Vec::new(); (map.width * map.height) as usize];
+        Some(map)
+    }
+}
+
+/// Take everything on the current level off the map so it stays put while the player is elsewhere
+pub fn freeze_level_entities(ecs: &mut World) {
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+    let players = ecs.read_storage::<Player>();
+    let depth = ecs.fetch::<Map>().depth;
+
+    let mut pos_to_delete: Vec<Entity> = Vec::new();
+    for (entity, pos, _) in (&entities, &positions, !&players).join() {
+        other_level_positions
+            .insert(
+                entity,
+                OtherLevelPosition {
+                    x: pos.x,
+                    y: pos.y,
+                    depth,
+                },
+            )
+            .expect("Insert fail");
+        pos_to_delete.push(entity);
+    }
+
+    for entity in pos_to_delete.iter() {
+        positions.remove(*entity);
+    }
+}
+
+/// Put everything that was frozen on the given level back on the map
+pub fn thaw_level_entities(ecs: &mut World, depth: i32) {
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+
+    let mut pos_to_delete: Vec<Entity> = Vec::new();
+    for (entity, pos) in (&entities, &other_level_positions).join() {
+        if pos.depth == depth {
+            positions
+                .insert(entity, Position { x: pos.x, y: pos.y })
+                .expect("Insert fail");
+            // monsters need to look around again before they act
+            if let Some(vs) = viewsheds.get_mut(entity) {
+                vs.dirty = true;
+            }
+            pos_to_delete.push(entity);
+        }
+    }
+
+    for entity in pos_to_delete.iter() {
+        other_level_positions.remove(*entity);
+    }
+}
+
+/// The walkable tile closest to `wanted` that no blocking entity stands on, so the player
+/// never arrives on top of a monster that was thawed there. Falls back to `wanted` itself
+/// when the whole level is full.
+pub fn free_arrival_tile(ecs: &World, map: &mut Map, wanted: Point) -> Point {
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let blockers = ecs.read_storage::<BlocksTiles>();
+    let players = ecs.read_storage::<Player>();
+    let occupied: HashSet<usize> = (&entities, &positions, &blockers, !&players)
+        .join()
+        .map(|(_, pos, _, _)| map.xy_idx(pos.x, pos.y))
+        .collect();
+
+    map.populate_blocked();
+    let start_idx = map.xy_idx(wanted.x, wanted.y);
+    let mut seen = HashSet::from([start_idx]);
+    let mut open = VecDeque::from([start_idx]);
+    while let Some(idx) = open.pop_front() {
+        if !occupied.contains(&idx) {
+            let (x, y) = map.idx_xy(idx);
+            return Point::new(x, y);
+        }
+        for (exit, _cost) in map.get_available_exits(idx) {
+            if seen.insert(exit) {
+                open.push_back(exit);
+            }
+        }
+    }
+    wanted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TileType;
+
+    #[test]
+    fn arriving_on_a_monster_steps_aside() {
+        let mut ecs = World::new();
+        ecs.register::<Position>();
+        ecs.register::<BlocksTiles>();
+        ecs.register::<Player>();
+        let mut map = Map::new(10, 10, 2);
+        for y in 1..9 {
+            for x in 1..9 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            }
+        }
+
+        let free = free_arrival_tile(&ecs, &mut map, Point::new(4, 4));
+        assert_eq!((free.x, free.y), (4, 4));
+
+        ecs.create_entity()
+            .with(Position { x: 4, y: 4 })
+            .with(BlocksTiles {})
+            .build();
+        let free = free_arrival_tile(&ecs, &mut map, Point::new(4, 4));
+        assert_ne!((free.x, free.y), (4, 4));
+        assert!((free.x - 4).abs() <= 1 && (free.y - 4).abs() <= 1);
+    }
+}
@@ -0,0 +1,45 @@
+use rltk::RGB;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// the turn the entry was written on
+    pub turn: i32,
+    pub text: String,
+    #[serde(with = "crate::saveload_system::rgb_hex")]
+    pub color: RGB,
+}
+
+/// Every gameplay message shown to the player, oldest first
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameLog {
+    pub entries: Vec<LogEntry>,
+    pub turn: i32,
+}
+
+impl GameLog {
+    pub fn new() -> GameLog {
+        GameLog {
+            entries: Vec::new(),
+            turn: 0,
+        }
+    }
+
+    pub fn log<S: ToString>(&mut self, text: S, color: RGB) {
+        self.entries.push(LogEntry {
+            turn: self.turn,
+            text: text.to_string(),
+            color,
+        });
+    }
+
+    pub fn advance_turn(&mut self) {
+        self.turn += 1;
+    }
+}
+
+impl Default for GameLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,578 @@
+use super::{camera, Activity, CombatStats, Confusion, GameLog, InBackpack, Map, Name, Viewshed};
+use rltk::{Point, Rltk, VirtualKeyCode, RGB};
+use specs::prelude::*;
+
+/// The panel below the map viewport
+const PANEL_Y: i32 = camera::VIEW_HEIGHT;
+const PANEL_HEIGHT: i32 = 6;
+const PANEL_WIDTH: i32 = camera::VIEW_WIDTH;
+
+/// Draw the bottom-of-screen panel with the player's stats and the most recent log entries,
+/// and tooltips over the map
+pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
+    ctx.draw_box(
+        0,
+        PANEL_Y,
+        PANEL_WIDTH - 1,
+        PANEL_HEIGHT,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+
+    let player_entity = ecs.fetch::<Entity>();
+    let log = ecs.fetch::<GameLog>();
+
+    // where and when, written into the top border
+    let depth = ecs.fetch::<Map>().depth;
+    ctx.print_color(
+        2,
+        PANEL_Y,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!(" Depth: {} ", depth),
+    );
+    ctx.print_color(
+        14,
+        PANEL_Y,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!(" Turn: {} ", log.turn),
+    );
+
+    if let Some(stats) = ecs.read_storage::<CombatStats>().get(*player_entity) {
+        let health = format!(" HP: {} / {} ", stats.hp, stats.max_hp);
+        ctx.print_color(
+            2,
+            PANEL_Y + 1,
+            RGB::named(rltk::YELLOW),
+            RGB::named(rltk::BLACK),
+            &health,
+        );
+        ctx.draw_bar_horizontal(
+            18,
+            PANEL_Y + 1,
+            30,
+            stats.hp,
+            stats.max_hp,
+            RGB::named(rltk::RED),
+            RGB::named(rltk::BLACK),
+        );
+    }
+
+    // anything currently affecting the player, to the right of the health bar
+    let mut statuses: Vec<(String, RGB)> = Vec::new();
+    if let Some(confusion) = ecs.read_storage::<Confusion>().get(*player_entity) {
+        statuses.push((
+            format!("Confused ({})", confusion.turns),
+            RGB::named(rltk::PINK),
+        ));
+    }
+    match ecs.read_storage::<Activity>().get(*player_entity) {
+        Some(Activity::Resting) => statuses.push(("Resting".to_string(), RGB::named(rltk::CYAN))),
+        Some(Activity::Exploring) => {
+            statuses.push(("Exploring".to_string(), RGB::named(rltk::CYAN)))
+        }
+        None => {}
+    }
+    let mut x = 50;
+    for (status, color) in statuses.iter() {
+        ctx.print_color(x, PANEL_Y + 1, *color, RGB::named(rltk::BLACK), status);
+        x += status.len() as i32 + 1;
+    }
+
+    let lines = (PANEL_HEIGHT - 2) as usize;
+    let recent = log.entries.iter().rev().take(lines).rev();
+    for (y, entry) in (PANEL_Y + 2..).zip(recent) {
+        ctx.print_color(2, y, entry.color, RGB::named(rltk::BLACK), &entry.text);
+    }
+
+    draw_tooltips(ecs, ctx);
+}
+
+/// Name everything on the visible tile under the mouse, with health for anything that has it
+fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
+    let map = ecs.fetch::<Map>();
+    let names = ecs.read_storage::<Name>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+
+    let mouse = Point::from_tuple(ctx.mouse_pos());
+    if mouse.x < 0 || mouse.x >= camera::VIEW_WIDTH || mouse.y < 0 || mouse.y >= camera::VIEW_HEIGHT
+    {
+        return;
+    }
+    let world = camera::get_viewport(ecs).screen_to_world(mouse);
+    if world.x < 0 || world.x >= map.width || world.y < 0 || world.y >= map.height {
+        return;
+    }
+    // only what the player can see right now, so nothing hidden leaks out
+    if !map.visible_tiles[map.xy_idx(world.x, world.y)] {
+        return;
+    }
+
+    let tooltip: Vec<String> = map
+        .entities_at(world.x, world.y)
+        .iter()
+        .filter_map(|entity| {
+            let name = names.get(*entity)?;
+            Some(match combat_stats.get(*entity) {
+                Some(stats) => format!("{} ({}/{} hp)", name.name, stats.hp, stats.max_hp),
+                None => name.name.clone(),
+            })
+        })
+        .collect();
+    if tooltip.is_empty() {
+        return;
+    }
+
+    // open the box away from the nearest screen edge so it stays readable
+    let width = tooltip.iter().map(|line| line.len()).max().unwrap_or(0) as i32 + 3;
+    let height = tooltip.len() as i32 + 1;
+    let left = if mouse.x > camera::VIEW_WIDTH / 2 {
+        mouse.x - width - 1
+    } else {
+        mouse.x + 1
+    };
+    let top = mouse.y.min(camera::VIEW_HEIGHT - height - 1);
+
+    ctx.set_bg(mouse.x, mouse.y, RGB::named(rltk::MAGENTA));
+    ctx.draw_box(
+        left,
+        top,
+        width,
+        height,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    for (y, line) in (top + 1..).zip(tooltip.iter()) {
+        ctx.print_color(
+            left + 2,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+}
+
+pub enum LogViewResult {
+    NoResponse,
+    Scroll(usize),
+    Close,
+}
+
+/// Draw the full message history on top of the map. `offset` is how many entries
+/// the view has been scrolled back from the newest one.
+pub fn show_log(ecs: &World, ctx: &mut Rltk, offset: usize) -> LogViewResult {
+    const X: i32 = 2;
+    const Y: i32 = 2;
+    const WIDTH: i32 = 75;
+    const HEIGHT: i32 = 39;
+
+    let log = ecs.fetch::<GameLog>();
+    let lines = (HEIGHT - 1) as usize;
+    let max_offset = log.entries.len().saturating_sub(lines);
+    let offset = offset.min(max_offset);
+
+    ctx.draw_box(
+        X,
+        Y,
+        WIDTH,
+        HEIGHT,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        X + 3,
+        Y,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Message Log",
+    );
+    ctx.print_color(
+        X + 3,
+        Y + HEIGHT,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Up/Down/PgUp/PgDn to scroll, ESC to close",
+    );
+
+    let end = log.entries.len() - offset;
+    let start = end.saturating_sub(lines);
+    for (y, entry) in (Y + 1..).zip(log.entries[start..end].iter()) {
+        ctx.print_color(
+            X + 2,
+            y,
+            RGB::named(rltk::GREY),
+            RGB::named(rltk::BLACK),
+            format!("{:>5}", entry.turn),
+        );
+        ctx.print_color(X + 8, y, entry.color, RGB::named(rltk::BLACK), &entry.text);
+    }
+
+    match ctx.key {
+        None => LogViewResult::NoResponse,
+        Some(key) => match key {
+            VirtualKeyCode::Escape | VirtualKeyCode::M => LogViewResult::Close,
+            VirtualKeyCode::Up | VirtualKeyCode::K | VirtualKeyCode::Numpad8 => {
+                LogViewResult::Scroll((offset + 1).min(max_offset))
+            }
+            VirtualKeyCode::Down | VirtualKeyCode::J | VirtualKeyCode::Numpad2 => {
+                LogViewResult::Scroll(offset.saturating_sub(1))
+            }
+            VirtualKeyCode::PageUp => LogViewResult::Scroll((offset + lines).min(max_offset)),
+            VirtualKeyCode::PageDown => LogViewResult::Scroll(offset.saturating_sub(lines)),
+            _ => LogViewResult::NoResponse,
+        },
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum ItemMenuResult {
+    Cancel,
+    NoResponse,
+    Selected,
+}
+
+/// Draw a lettered list of everything in the player's backpack and wait for a choice
+fn item_menu(ecs: &World, ctx: &mut Rltk, title: &str) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let entities = ecs.entities();
+
+    let inventory: Vec<(Entity, &Name)> = (&entities, &backpack, &names)
+        .join()
+        .filter(|(_entity, pack, _name)| pack.owner == *player_entity)
+        .map(|(entity, _pack, name)| (entity, name))
+        .collect();
+    let count = inventory.len() as i32;
+
+    let top = 25 - (count / 2);
+    ctx.draw_box(
+        15,
+        top - 2,
+        31,
+        count + 3,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        18,
+        top - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        title,
+    );
+    ctx.print_color(
+        18,
+        top + count + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "ESCAPE to cancel",
+    );
+
+    for (j, (_entity, name)) in inventory.iter().enumerate() {
+        let y = top + j as i32;
+        ctx.set(
+            17,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('('),
+        );
+        ctx.set(
+            18,
+            y,
+            RGB::named(rltk::YELLOW),
+            RGB::named(rltk::BLACK),
+            97 + j as rltk::FontCharType,
+        );
+        ctx.set(
+            19,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437(')'),
+        );
+        ctx.print(21, y, &name.name);
+    }
+
+    match ctx.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(VirtualKeyCode::Escape) => (ItemMenuResult::Cancel, None),
+        Some(key) => {
+            let selection = rltk::letter_to_option(key);
+            if selection > -1 && selection < count {
+                (
+                    ItemMenuResult::Selected,
+                    Some(inventory[selection as usize].0),
+                )
+            } else {
+                (ItemMenuResult::NoResponse, None)
+            }
+        }
+    }
+}
+
+pub fn show_inventory(ecs: &World, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+    item_menu(ecs, ctx, "Inventory")
+}
+
+pub fn drop_item_menu(ecs: &World, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+    item_menu(ecs, ctx, "Drop Which Item?")
+}
+
+/// Targeting mode: highlight every visible tile within range and let the player click one
+pub fn ranged_target(ecs: &World, ctx: &mut Rltk, range: i32) -> (ItemMenuResult, Option<Point>) {
+    let player_entity = ecs.fetch::<Entity>();
+    let player_pos = ecs.fetch::<Point>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let viewport = camera::get_viewport(ecs);
+
+    ctx.print_color(
+        5,
+        0,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Select Target (ESCAPE to cancel)",
+    );
+
+    if ctx.key == Some(VirtualKeyCode::Escape) {
+        return (ItemMenuResult::Cancel, None);
+    }
+
+    // only tiles the player can currently see are valid targets
+    let mut available_cells = Vec::new();
+    if let Some(visible) = viewsheds.get(*player_entity) {
+        for tile in visible.visible_tiles.iter() {
+            let distance = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *tile);
+            if distance <= range as f32 {
+                if let Some(screen) = viewport.world_to_screen(*tile) {
+                    ctx.set_bg(screen.x, screen.y, RGB::named(rltk::BLUE));
+                    available_cells.push(*tile);
+                }
+            }
+        }
+    } else {
+        return (ItemMenuResult::Cancel, None);
+    }
+
+    let (mouse_x, mouse_y) = ctx.mouse_pos();
+    let mouse_world = viewport.screen_to_world(Point::new(mouse_x, mouse_y));
+    if available_cells.contains(&mouse_world) {
+        ctx.set_bg(mouse_x, mouse_y, RGB::named(rltk::CYAN));
+        if ctx.left_click {
+            return (ItemMenuResult::Selected, Some(mouse_world));
+        }
+    } else {
+        ctx.set_bg(mouse_x, mouse_y, RGB::named(rltk::RED));
+        if ctx.left_click {
+            return (ItemMenuResult::Cancel, None);
+        }
+    }
+
+    (ItemMenuResult::NoResponse, None)
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum MainMenuSelection {
+    NewGame,
+    Continue,
+    ToggleVisualiser,
+    Quit,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum PauseMenuSelection {
+    Resume,
+    SaveAndMenu,
+    SaveAndQuit,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum MenuResult<T> {
+    NoSelection { selected: T },
+    Selected { selected: T },
+    Cancel,
+}
+
+/// Draw a vertical list of options, moving the highlight with the arrow keys
+fn menu<T: PartialEq + Copy>(
+    ctx: &mut Rltk,
+    top: i32,
+    entries: &[(T, &str)],
+    selection: T,
+) -> MenuResult<T> {
+    let current = entries
+        .iter()
+        .position(|(entry, _)| *entry == selection)
+        .unwrap_or(0);
+
+    for (i, (_entry, label)) in entries.iter().enumerate() {
+        let fg = if i == current {
+            RGB::named(rltk::MAGENTA)
+        } else {
+            RGB::named(rltk::WHITE)
+        };
+        ctx.print_color_centered(top + i as i32, fg, RGB::named(rltk::BLACK), *label);
+    }
+
+    match ctx.key {
+        None => MenuResult::NoSelection {
+            selected: entries[current].0,
+        },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => MenuResult::Cancel,
+            VirtualKeyCode::Up | VirtualKeyCode::K | VirtualKeyCode::Numpad8 => {
+                let previous = (current + entries.len() - 1) % entries.len();
+                MenuResult::NoSelection {
+                    selected: entries[previous].0,
+                }
+            }
+            VirtualKeyCode::Down | VirtualKeyCode::J | VirtualKeyCode::Numpad2 => {
+                let next = (current + 1) % entries.len();
+                MenuResult::NoSelection {
+                    selected: entries[next].0,
+                }
+            }
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => MenuResult::Selected {
+                selected: entries[current].0,
+            },
+            _ => MenuResult::NoSelection {
+                selected: entries[current].0,
+            },
+        },
+    }
+}
+
+pub fn main_menu(
+    ctx: &mut Rltk,
+    selection: MainMenuSelection,
+    save_exists: bool,
+    visualise_mapgen: bool,
+    notice: Option<&str>,
+) -> MenuResult<MainMenuSelection> {
+    ctx.print_color_centered(
+        15,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Rust RL",
+    );
+    if let Some(notice) = notice {
+        ctx.print_color_centered(18, RGB::named(rltk::RED), RGB::named(rltk::BLACK), notice);
+    }
+
+    let visualiser_label = format!(
+        "(V) Show Map Generation: {}",
+        if visualise_mapgen { "On" } else { "Off" }
+    );
+    let mut entries = vec![(MainMenuSelection::NewGame, "Begin New Game")];
+    if save_exists {
+        entries.push((MainMenuSelection::Continue, "Continue"));
+    }
+    entries.push((
+        MainMenuSelection::ToggleVisualiser,
+        visualiser_label.as_str(),
+    ));
+    entries.push((MainMenuSelection::Quit, "Quit"));
+
+    // the visualiser can be flipped from anywhere in the menu
+    if ctx.key == Some(VirtualKeyCode::V) {
+        return MenuResult::Selected {
+            selected: MainMenuSelection::ToggleVisualiser,
+        };
+    }
+
+    menu(ctx, 24, &entries, selection)
+}
+
+pub fn pause_menu(ctx: &mut Rltk, selection: PauseMenuSelection) -> MenuResult<PauseMenuSelection> {
+    ctx.draw_box(
+        25,
+        18,
+        29,
+        8,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color_centered(
+        20,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Paused",
+    );
+
+    let entries = [
+        (PauseMenuSelection::Resume, "Resume"),
+        (PauseMenuSelection::SaveAndMenu, "Save and Exit to Menu"),
+        (PauseMenuSelection::SaveAndQuit, "Save and Quit"),
+    ];
+    menu(ctx, 22, &entries, selection)
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum GameOverResult {
+    NoSelection,
+    QuitToMenu,
+}
+
+pub fn game_over(ctx: &mut Rltk, cause: &str, turns: i32) -> GameOverResult {
+    ctx.print_color_centered(
+        15,
+        RGB::named(rltk::RED),
+        RGB::named(rltk::BLACK),
+        "Your journey has ended!",
+    );
+    ctx.print_color_centered(
+        18,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+        format!("Killed by {}", cause),
+    );
+    ctx.print_color_centered(
+        19,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+        format!("You survived {} turns", turns),
+    );
+    ctx.print_color_centered(
+        22,
+        RGB::named(rltk::MAGENTA),
+        RGB::named(rltk::BLACK),
+        "Press any key to return to the menu.",
+    );
+
+    match ctx.key {
+        None => GameOverResult::NoSelection,
+        Some(_) => GameOverResult::QuitToMenu,
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum MapGenResult {
+    NoResponse,
+    Skip,
+}
+
+/// Caption shown over the map generation playback
+pub fn map_generation(ctx: &mut Rltk, step: usize, steps: usize) -> MapGenResult {
+    ctx.print_color(
+        1,
+        PANEL_Y + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!("Generating level: step {} of {}", step + 1, steps),
+    );
+    ctx.print_color(
+        1,
+        PANEL_Y + 2,
+        RGB::named(rltk::GREY),
+        RGB::named(rltk::BLACK),
+        "Press ESC to skip",
+    );
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => MapGenResult::Skip,
+        _ => MapGenResult::NoResponse,
+    }
+}
@@ -0,0 +1,321 @@
+use super::cli::{CliOptions, OutputFormat};
+use super::{
+    generate_world_map, map_builders, raws, register_components, Map, Name, Position, Rect,
+    Renderable, TileType,
+};
+use rltk::RandomNumberGenerator;
+use serde::Serialize;
+use specs::prelude::*;
+use std::io::Write;
+
+/// Everything `--generate` reports about one level
+#[derive(Serialize)]
+struct GeneratedLevel {
+    seed: u64,
+    builder: String,
+    depth: i32,
+    width: i32,
+    height: i32,
+    /// separate walkable regions before unreachable ones were walled off
+    regions: usize,
+    start: Spawn,
+    /// one string per row, drawn with the same glyphs as the game
+    tiles: Vec<String>,
+    rooms: Vec<Rect>,
+    spawns: Vec<Spawn>,
+}
+
+#[derive(Serialize)]
+struct Spawn {
+    name: String,
+    x: i32,
+    y: i32,
+    #[serde(skip)]
+    glyph: char,
+}
+
+/// Build `options.count` levels from consecutive seeds and write them to stdout without opening a window.
+/// JSON output is one object per line so large batches can be streamed.
+pub fn run(options: &CliOptions, raws: &raws::RawMaster) -> Result<(), String> {
+    let stdout = std::io::stdout();
+    write_levels(&mut stdout.lock(), options, raws)
+}
+
+fn write_levels(
+    out: &mut impl Write,
+    options: &CliOptions,
+    raws: &raws::RawMaster,
+) -> Result<(), String> {
+    let first_seed = options
+        .seed
+        .unwrap_or_else(|| RandomNumberGenerator::new().next_u64());
+
+    for i in 0..options.count {
+        let seed = first_seed.wrapping_add(i);
+        let level = generate_level(seed, options, raws);
+        match options.format {
+            OutputFormat::Ascii => write_ascii(out, &level),
+            OutputFormat::Json => serde_json::to_writer(&mut *out, &level)
+                .map_err(|e| e.to_string())
+                .and_then(|_| writeln!(out).map_err(|e| e.to_string())),
+        }
+        .map_err(|e| format!("Unable to write level for seed {}: {}", seed, e))?;
+    }
+
+    Ok(())
+}
+
+/// Generate a level from a fresh generator seeded with `seed`, in a throwaway world. At depth 1
+/// this is the first level of a new game with the same seed; deeper levels in a game are drawn
+/// from a generator that has already built every level above, so they won't match.
+fn generate_level(seed: u64, options: &CliOptions, raws: &raws::RawMaster) -> GeneratedLevel {
+    let depth = options.depth;
+    let mut ecs = World::new();
+    register_components(&mut ecs);
+    ecs.insert(options.corner_cutting);
+    ecs.insert(raws.clone());
+    let mut rng = RandomNumberGenerator::seeded(seed);
+    // draw the builder the same way a new game does, so depth 1 matches the game's first level
+    let builder = match options.builder.as_deref() {
+        Some(name) => name.to_string(),
+        None => map_builders::random_builder_name(&mut rng).to_string(),
+    };
+    ecs.insert(rng);
+
+    let generated = generate_world_map(&mut ecs, depth, Some(&builder));
+    let (start_x, start_y) = generated.start;
+    let map = ecs.fetch::<Map>();
+
+    let positions = ecs.read_storage::<Position>();
+    let names = ecs.read_storage::<Name>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let spawns = (&positions, &names, &renderables)
+        .join()
+        .map(|(pos, name, render)| Spawn {
+            name: name.name.clone(),
+            x: pos.x,
+            y: pos.y,
+            glyph: rltk::to_char(render.glyph as u8),
+        })
+        .collect();
+
+    let tiles = (0..map.height)
+        .map(|y| {
+            (0..map.width)
+                .map(|x| tile_glyph(map.tiles[map.xy_idx(x, y)]))
+                .collect()
+        })
+        .collect();
+
+    GeneratedLevel {
+        seed,
+        builder,
+        depth,
+        width: map.width,
+        height: map.height,
+        regions: generated.regions,
+        start: Spawn {
+            name: "Player".to_string(),
+            x: start_x,
+            y: start_y,
+            glyph: '@',
+        },
+        tiles,
+        rooms: map.rooms.clone(),
+        spawns,
+    }
+}
+
+/// The same glyph the game draws for a tile
+fn tile_glyph(tile: TileType) -> char {
+    match tile {
+        TileType::Floor => '.',
+        TileType::Wall => '#',
+        TileType::DownStairs => '>',
+        TileType::UpStairs => '<',
+    }
+}
+
+/// The map with the player start and spawns drawn over it, followed by the rooms and spawn list
+fn write_ascii(out: &mut impl Write, level: &GeneratedLevel) -> Result<(), String> {
+    let mut rows: Vec<Vec<char>> = level
+        .tiles
+        .iter()
+        .map(|row| row.chars().collect())
+        .collect();
+    for spawn in level.spawns.iter().chain(std::iter::once(&level.start)) {
+        rows[spawn.y as usize][spawn.x as usize] = spawn.glyph;
+    }
+
+    let mut text = format!(
+        "seed {} builder {} depth {} size {}x{} regions {}\n",
+        level.seed, level.builder, level.depth, level.width, level.height, level.regions
+    );
+    for row in rows.iter() {
+        text.extend(row.iter());
+        text.push('\n');
+    }
+    for room in level.rooms.iter() {
+        text.push_str(&format!(
+            "room {},{} {},{}\n",
+            room.x1, room.y1, room.x2, room.y2
+        ));
+    }
+    for spawn in std::iter::once(&level.start).chain(level.spawns.iter()) {
+        text.push_str(&format!("spawn {} {},{}\n", spawn.name, spawn.x, spawn.y));
+    }
+    text.push('\n');
+
+    out.write_all(text.as_bytes()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_args;
+
+    fn options(args: &[&str]) -> CliOptions {
+        parse_args(args.iter().map(|arg| arg.to_string())).expect("Invalid test arguments")
+    }
+
+    fn raws() -> raws::RawMaster {
+        raws::RawMaster::embedded().expect("Built-in raws are valid")
+    }
+
+    fn output(args: &[&str]) -> String {
+        let mut out = Vec::new();
+        write_levels(&mut out, &options(args), &raws()).expect("Unable to write levels");
+        String::from_utf8(out).expect("Output is not UTF-8")
+    }
+
+    #[test]
+    fn ascii_output_is_a_header_then_the_map_rooms_and_spawns() {
+        let args = ["--generate", "--seed", "7", "--builder", "simple"];
+        let text = output(&args);
+        let level = generate_level(7, &options(&args), &raws());
+        let mut lines = text.lines();
+
+        assert_eq!(
+            lines.next(),
+            Some(
+                format!(
+                    "seed 7 builder simple depth 1 size {}x{} regions {}",
+                    level.width, level.height, level.regions
+                )
+                .as_str()
+            )
+        );
+        for y in 0..level.height {
+            let row: Vec<char> = lines.next().expect("Map is cut short").chars().collect();
+            assert_eq!(row.len(), level.width as usize);
+            if y == level.start.y {
+                assert_eq!(row[level.start.x as usize], '@');
+            }
+        }
+        for room in level.rooms.iter() {
+            assert_eq!(
+                lines.next(),
+                Some(format!("room {},{} {},{}", room.x1, room.y1, room.x2, room.y2).as_str())
+            );
+        }
+        assert_eq!(
+            lines.next(),
+            Some(format!("spawn Player {},{}", level.start.x, level.start.y).as_str())
+        );
+        for spawn in level.spawns.iter() {
+            assert_eq!(
+                lines.next(),
+                Some(format!("spawn {} {},{}", spawn.name, spawn.x, spawn.y).as_str())
+            );
+        }
+        assert_eq!(lines.next(), Some(""));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn json_output_is_one_level_per_line_and_round_trips() {
+        let args = [
+            "--generate",
+            "--seed",
+            "7",
+            "--count",
+            "3",
+            "--format",
+            "json",
+        ];
+        let text = output(&args);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+
+        for (seed, line) in (7..).zip(lines) {
+            let parsed: serde_json::Value = serde_json::from_str(line).expect("Line is not JSON");
+            let level = generate_level(seed, &options(&args), &raws());
+
+            assert_eq!(parsed["seed"], seed);
+            let tiles: Vec<String> = serde_json::from_value(parsed["tiles"].clone())
+                .expect("tiles are not a list of rows");
+            assert_eq!(tiles, level.tiles);
+            let rooms: Vec<Rect> =
+                serde_json::from_value(parsed["rooms"].clone()).expect("rooms are not rects");
+            assert_eq!(rooms.len(), level.rooms.len());
+            for (room, expected) in rooms.iter().zip(level.rooms.iter()) {
+                assert_eq!(
+                    (room.x1, room.y1, room.x2, room.y2),
+                    (expected.x1, expected.y1, expected.x2, expected.y2)
+                );
+            }
+            let spawns = parsed["spawns"].as_array().expect("spawns are not a list");
+            assert_eq!(spawns.len(), level.spawns.len());
+            for (spawn, expected) in spawns.iter().zip(level.spawns.iter()) {
+                assert_eq!(spawn["name"], expected.name.as_str());
+                assert_eq!(
+                    (spawn["x"].clone(), spawn["y"].clone()),
+                    (expected.x.into(), expected.y.into())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn depth_one_matches_the_first_level_of_a_new_game() {
+        for seed in 0..5 {
+            let level = generate_level(seed, &options(&["--generate"]), &raws());
+
+            let mut ecs = World::new();
+            register_components(&mut ecs);
+            ecs.insert(crate::CornerCutting::default());
+            ecs.insert(raws());
+            crate::new_game(&mut ecs, seed, None);
+
+            let map = ecs.fetch::<Map>();
+            let tiles: Vec<String> = (0..map.height)
+                .map(|y| {
+                    (0..map.width)
+                        .map(|x| tile_glyph(map.tiles[map.xy_idx(x, y)]))
+                        .collect()
+                })
+                .collect();
+            assert_eq!(tiles, level.tiles, "seed {} built a different map", seed);
+
+            let player = *ecs.fetch::<Entity>();
+            let positions = ecs.read_storage::<Position>();
+            let player_pos = positions.get(player).expect("The player has no position");
+            assert_eq!((player_pos.x, player_pos.y), (level.start.x, level.start.y));
+
+            let names = ecs.read_storage::<Name>();
+            let mut in_game: Vec<(String, i32, i32)> = (&ecs.entities(), &positions, &names)
+                .join()
+                .filter(|(entity, _, _)| *entity != player)
+                .map(|(_, pos, name)| (name.name.clone(), pos.x, pos.y))
+                .collect();
+            let mut generated: Vec<(String, i32, i32)> = level
+                .spawns
+                .iter()
+                .map(|spawn| (spawn.name.clone(), spawn.x, spawn.y))
+                .collect();
+            in_game.sort();
+            generated.sort();
+            assert_eq!(in_game, generated, "seed {} spawned different things", seed);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use super::{
+    AreaOfEffect, CombatStats, Confusion, Consumable, GameLog, InBackpack, InflictsDamage, Map,
+    Name, Position, ProvidesHealing, SufferDamage, WantsToDropItem, WantsToPickupItem,
+    WantsToUseItem,
+};
+use rltk::{field_of_view, RGB};
+use specs::prelude::*;
+
+pub struct ItemCollectionSystem {}
+
+impl<'a> System<'a> for ItemCollectionSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, WantsToPickupItem>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, InBackpack>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            s_player_entity,
+            mut s_log,
+            mut s_wants_pickup,
+            mut s_positions,
+            s_names,
+            mut s_backpack,
+        ) = data;
+
+        for pickup in s_wants_pickup.join() {
+            // an item in a backpack has no place on the map
+            s_positions.remove(pickup.item);
+            s_backpack
+                .insert(
+                    pickup.item,
+                    InBackpack {
+                        owner: pickup.collected_by,
+                    },
+                )
+                .expect("Unable to insert backpack entry");
+
+            if pickup.collected_by == *s_player_entity {
+                if let Some(name) = s_names.get(pickup.item) {
+                    s_log.log(
+                        format!("You pick up the {}.", name.name),
+                        RGB::named(rltk::CYAN),
+                    );
+                }
+            }
+        }
+
+        s_wants_pickup.clear();
+    }
+}
+
+pub struct ItemDropSystem {}
+
+impl<'a> System<'a> for ItemDropSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToDropItem>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, InBackpack>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            s_player_entity,
+            mut s_log,
+            entities,
+            mut s_wants_drop,
+            s_names,
+            mut s_positions,
+            mut s_backpack,
+        ) = data;
+
+        for (entity, to_drop) in (&entities, &s_wants_drop).join() {
+            // the item lands on the tile the dropper is standing on
+            let dropper_pos = match s_positions.get(entity) {
+                Some(pos) => Position { x: pos.x, y: pos.y },
+                None => continue,
+            };
+            s_positions
+                .insert(to_drop.item, dropper_pos)
+                .expect("Unable to insert position");
+            s_backpack.remove(to_drop.item);
+
+            if entity == *s_player_entity {
+                if let Some(name) = s_names.get(to_drop.item) {
+                    s_log.log(
+                        format!("You drop the {}.", name.name),
+                        RGB::named(rltk::CYAN),
+                    );
+                }
+            }
+        }
+
+        s_wants_drop.clear();
+    }
+}
+
+pub struct ItemUseSystem {}
+
+impl<'a> System<'a> for ItemUseSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Consumable>,
+        ReadStorage<'a, ProvidesHealing>,
+        ReadStorage<'a, InflictsDamage>,
+        ReadStorage<'a, AreaOfEffect>,
+        WriteStorage<'a, Confusion>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            s_player_entity,
+            mut s_log,
+            s_map,
+            entities,
+            mut s_wants_use,
+            s_names,
+            s_consumables,
+            s_healing,
+            s_inflict_damage,
+            s_aoe,
+            mut s_confused,
+            mut s_combat_stats,
+            mut s_suffer_damage,
+        ) = data;
+
+        for (user, use_item) in (&entities, &s_wants_use).join() {
+            let item_name = match s_names.get(use_item.item) {
+                Some(name) => name.name.clone(),
+                None => continue,
+            };
+            let by_player = user == *s_player_entity;
+
+            // work out which entities the effects land on
+            let mut targets: Vec<Entity> = Vec::new();
+            match use_item.target {
+                None => targets.push(user),
+                Some(target) => match s_aoe.get(use_item.item) {
+                    None => targets.extend_from_slice(s_map.entities_at(target.x, target.y)),
+                    Some(area) => {
+                        // the blast doesn't pass through walls
+                        let mut blast_tiles = field_of_view(target, area.radius, &*s_map);
+                        blast_tiles.retain(|p| {
+                            p.x > 0 && p.x < s_map.width - 1 && p.y > 0 && p.y < s_map.height - 1
+                        });
+                        for tile in blast_tiles.iter() {
+                            targets.extend_from_slice(s_map.entities_at(tile.x, tile.y));
+                        }
+                    }
+                },
+            }
+
+            if let Some(healer) = s_healing.get(use_item.item) {
+                for target in targets.iter() {
+                    if let Some(stats) = s_combat_stats.get_mut(*target) {
+                        stats.hp = i32::min(stats.max_hp, stats.hp + healer.heal_amount);
+                        if by_player {
+                            s_log.log(
+                                format!(
+                                    "You use the {}, healing {} hp.",
+                                    item_name, healer.heal_amount
+                                ),
+                                RGB::named(rltk::GREEN),
+                            );
+                        }
+                    }
+                }
+            }
+
+            if let Some(damage) = s_inflict_damage.get(use_item.item) {
+                for target in targets.iter() {
+                    if s_combat_stats.get(*target).is_none() {
+                        continue;
+                    }
+                    SufferDamage::new_damage(
+                        &mut s_suffer_damage,
+                        *target,
+                        damage.damage,
+                        &item_name,
+                    );
+                    if by_player {
+                        if let Some(target_name) = s_names.get(*target) {
+                            s_log.log(
+                                format!(
+                                    "You use {} on {}, inflicting {} hp.",
+                                    item_name, target_name.name, damage.damage
+                                ),
+                                RGB::named(rltk::ORANGE),
+                            );
+                        }
+                    }
+                }
+            }
+
+            let confusion_turns = s_confused.get(use_item.item).map(|c| c.turns);
+            if let Some(turns) = confusion_turns {
+                for target in targets.iter() {
+                    if s_combat_stats.get(*target).is_none() {
+                        continue;
+                    }
+                    s_confused
+                        .insert(*target, Confusion { turns })
+                        .expect("Unable to insert status");
+                    if by_player {
+                        if let Some(target_name) = s_names.get(*target) {
+                            s_log.log(
+                                format!(
+                                    "You use {} on {}, confusing them.",
+                                    item_name, target_name.name
+                                ),
+                                RGB::named(rltk::PINK),
+                            );
+                        }
+                    }
+                }
+            }
+
+            if s_consumables.get(use_item.item).is_some() {
+                entities.delete(use_item.item).expect("Delete failed");
+            }
+        }
+
+        s_wants_use.clear();
+    }
+}
@@ -1,8 +1,10 @@
-use rltk::{GameState, Point, RandomNumberGenerator, Rltk, RGB};
+use rltk::{console, BEvent, GameState, Point, RandomNumberGenerator, Rltk, RGB};
 use specs::prelude::*;
+use specs::saveload::{SimpleMarker, SimpleMarkerAllocator};
 
 mod map;
 pub use map::*;
+mod map_builders;
 mod map_indexing_system;
 pub use map_indexing_system::*;
 mod components;
@@ -15,153 +17,559 @@ mod visibility_system;
 use visibility_system::VisibilitySystem;
 mod monster_ai_system;
 pub use monster_ai_system::*;
+mod melee_combat_system;
+use melee_combat_system::MeleeCombatSystem;
+mod damage_system;
+use damage_system::DamageSystem;
+mod dungeon;
+pub use dungeon::*;
+mod camera;
+mod cli;
+mod gamelog;
+pub use gamelog::*;
+mod gui;
+mod headless;
+mod inventory_system;
+use inventory_system::{ItemCollectionSystem, ItemDropSystem, ItemUseSystem};
+mod noise;
+pub use noise::*;
+mod raws;
+mod saveload_system;
+mod spawn_table;
+mod spawner;
+
+pub const MAP_WIDTH: i32 = 120;
+pub const MAP_HEIGHT: i32 = 70;
+/// How long each map generation snapshot stays on screen
+const MAPGEN_FRAME_MS: f32 = 150.0;
 
 // STATE
 #[derive(PartialEq, Clone, Copy)]
 pub enum RunState {
     Paused,
     Running,
+    ShowLog { offset: usize },
+    ShowInventory,
+    ShowDropItem,
+    ShowTargeting { range: i32, item: Entity },
+    NextLevel,
+    PreviousLevel,
+    MapGeneration,
+    MainMenu { selection: gui::MainMenuSelection },
+    PauseMenu { selection: gui::PauseMenuSelection },
+    GameOver,
+}
+
+impl RunState {
+    /// True while a dungeon is loaded in the world, as opposed to the title and death screens
+    fn in_game(&self) -> bool {
+        !matches!(self, RunState::MainMenu { .. } | RunState::GameOver)
+    }
 }
 
 pub struct State {
     pub ecs: World,
     pub runstate: RunState,
+    /// seed passed on the command line; new games roll their own when this is None
+    pub seed: Option<u64>,
+    /// map builder passed on the command line; levels pick one at random when this is None
+    pub builder: Option<String>,
+    /// play back each new level's generation before it is entered
+    pub visualise_mapgen: bool,
+    mapgen_history: Vec<Map>,
+    mapgen_index: usize,
+    mapgen_timer: f32,
+    /// the newest `GameLog` entry says why Continue failed, so the main menu shows it
+    load_failed: bool,
 }
 impl GameState for State {
     fn tick(&mut self, ctx: &mut Rltk) {
         ctx.cls();
 
-        if self.runstate == RunState::Running {
-            self.run_systems();
-            self.runstate = RunState::Paused;
-        } else {
-            self.runstate = player_input(self, ctx);
+        let mut close_requested = false;
+        rltk::INPUT.lock().for_each_message(|event| {
+            if let BEvent::CloseRequested = event {
+                close_requested = true;
+            }
+        });
+        if close_requested {
+            if self.runstate.in_game() {
+                self.save();
+            }
+            ctx.quit();
+            return;
+        }
+
+        if self.runstate.in_game() && self.runstate != RunState::MapGeneration {
+            camera::render_camera(&self.ecs, ctx);
+            gui::draw_ui(&self.ecs, ctx);
         }
 
-        let positions = self.ecs.read_storage::<Position>();
-        let renderables = self.ecs.read_storage::<Renderable>();
-        let map = self.ecs.fetch::<Map>();
-
-        // draw both visible and revealed tiles on the map
-        Map::draw_map(&self.ecs, ctx);
-        // draw all other renderables that are within the vec of visible tiles on the map
-        for (pos, render) in (&positions, &renderables).join() {
-            let xy_idx = map.xy_idx(pos.x, pos.y);
-            if map.visible_tiles[xy_idx] {
-                ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph);
+        let mut newrunstate = self.runstate;
+        match newrunstate {
+            RunState::MainMenu { selection } => {
+                let notice = if self.load_failed {
+                    self.ecs
+                        .fetch::<GameLog>()
+                        .entries
+                        .last()
+                        .map(|entry| entry.text.clone())
+                } else {
+                    None
+                };
+                match gui::main_menu(
+                    ctx,
+                    selection,
+                    saveload_system::does_save_exist(),
+                    self.visualise_mapgen,
+                    notice.as_deref(),
+                ) {
+                    gui::MenuResult::NoSelection { selected } => {
+                        newrunstate = RunState::MainMenu {
+                            selection: selected,
+                        }
+                    }
+                    gui::MenuResult::Selected { selected } => match selected {
+                        gui::MainMenuSelection::NewGame => {
+                            self.load_failed = false;
+                            self.start_new_game();
+                            newrunstate = self.play_mapgen_then(RunState::Running);
+                        }
+                        gui::MainMenuSelection::ToggleVisualiser => {
+                            self.visualise_mapgen = !self.visualise_mapgen;
+                            newrunstate = RunState::MainMenu { selection };
+                        }
+                        gui::MainMenuSelection::Continue => {
+                            match saveload_system::load_game(&mut self.ecs) {
+                                Ok(runstate) => {
+                                    self.load_failed = false;
+                                    newrunstate = runstate;
+                                }
+                                Err(e) => {
+                                    // there may be no game running yet to own a log
+                                    self.ecs
+                                        .entry::<GameLog>()
+                                        .or_insert_with(GameLog::new)
+                                        .log(
+                                            format!("Unable to load save: {}", e),
+                                            RGB::named(rltk::RED),
+                                        );
+                                    self.load_failed = true;
+                                }
+                            }
+                        }
+                        gui::MainMenuSelection::Quit => ctx.quit(),
+                    },
+                    gui::MenuResult::Cancel => {}
+                }
+            }
+            RunState::PauseMenu { selection } => match gui::pause_menu(ctx, selection) {
+                gui::MenuResult::NoSelection { selected } => {
+                    newrunstate = RunState::PauseMenu {
+                        selection: selected,
+                    }
+                }
+                gui::MenuResult::Selected { selected } => match selected {
+                    gui::PauseMenuSelection::Resume => newrunstate = RunState::Paused,
+                    // stay in the game if saving failed so the log can explain why
+                    gui::PauseMenuSelection::SaveAndMenu => {
+                        newrunstate = if self.save() {
+                            RunState::MainMenu {
+                                selection: gui::MainMenuSelection::Continue,
+                            }
+                        } else {
+                            RunState::Paused
+                        };
+                    }
+                    gui::PauseMenuSelection::SaveAndQuit => {
+                        if self.save() {
+                            ctx.quit();
+                        } else {
+                            newrunstate = RunState::Paused;
+                        }
+                    }
+                },
+                gui::MenuResult::Cancel => newrunstate = RunState::Paused,
+            },
+            RunState::GameOver => {
+                let cause = self
+                    .ecs
+                    .read_storage::<KilledBy>()
+                    .get(*self.ecs.fetch::<Entity>())
+                    .map(|killer| killer.name.clone())
+                    .unwrap_or_else(|| "unknown causes".to_string());
+                let turns = self.ecs.fetch::<GameLog>().turn;
+                if gui::game_over(ctx, &cause, turns) == gui::GameOverResult::QuitToMenu {
+                    newrunstate = RunState::MainMenu {
+                        selection: gui::MainMenuSelection::NewGame,
+                    };
+                }
+            }
+            RunState::Running => {
+                self.run_systems();
+                if damage_system::delete_the_dead(&mut self.ecs) {
+                    // permadeath: there is nothing left to continue
+                    if let Err(e) = saveload_system::delete_save() {
+                        self.ecs
+                            .write_resource::<GameLog>()
+                            .log(e, RGB::named(rltk::RED));
+                    }
+                    newrunstate = RunState::GameOver;
+                } else {
+                    newrunstate = RunState::Paused;
+                }
+            }
+            RunState::Paused => {
+                newrunstate = match continue_queued_movement(&mut self.ecs, ctx)
+                    .or_else(|| continue_activity(&mut self.ecs, ctx))
+                {
+                    Some(state) => state,
+                    None => player_input(self, ctx),
+                };
+            }
+            RunState::NextLevel => {
+                self.goto_level(1);
+                newrunstate = self.play_mapgen_then(RunState::Running);
+            }
+            RunState::PreviousLevel => {
+                self.goto_level(-1);
+                newrunstate = self.play_mapgen_then(RunState::Running);
+            }
+            RunState::MapGeneration => {
+                camera::render_map_snapshot(&self.mapgen_history[self.mapgen_index], ctx);
+                let skip = gui::map_generation(ctx, self.mapgen_index, self.mapgen_history.len())
+                    == gui::MapGenResult::Skip;
+
+                self.mapgen_timer += ctx.frame_time_ms;
+                if self.mapgen_timer > MAPGEN_FRAME_MS {
+                    self.mapgen_timer = 0.0;
+                    self.mapgen_index += 1;
+                }
+                if skip || self.mapgen_index >= self.mapgen_history.len() {
+                    self.mapgen_history.clear();
+                    newrunstate = RunState::Running;
+                }
+            }
+            RunState::ShowLog { offset } => match gui::show_log(&self.ecs, ctx, offset) {
+                gui::LogViewResult::NoResponse => {}
+                gui::LogViewResult::Scroll(offset) => newrunstate = RunState::ShowLog { offset },
+                gui::LogViewResult::Close => newrunstate = RunState::Paused,
+            },
+            RunState::ShowInventory => match gui::show_inventory(&self.ecs, ctx) {
+                (gui::ItemMenuResult::NoResponse, _) => {}
+                (gui::ItemMenuResult::Selected, Some(item)) => {
+                    // ranged items need a target before they can be used
+                    let range = self.ecs.read_storage::<Ranged>().get(item).map(|r| r.range);
+                    match range {
+                        Some(range) => newrunstate = RunState::ShowTargeting { range, item },
+                        None => {
+                            self.queue_item_use(item, None);
+                            newrunstate = RunState::Running;
+                        }
+                    }
+                }
+                _ => newrunstate = RunState::Paused,
+            },
+            RunState::ShowTargeting { range, item } => {
+                match gui::ranged_target(&self.ecs, ctx, range) {
+                    (gui::ItemMenuResult::NoResponse, _) => {}
+                    (gui::ItemMenuResult::Selected, Some(target)) => {
+                        self.queue_item_use(item, Some(target));
+                        newrunstate = RunState::Running;
+                    }
+                    _ => newrunstate = RunState::Paused,
+                }
             }
+            RunState::ShowDropItem => match gui::drop_item_menu(&self.ecs, ctx) {
+                (gui::ItemMenuResult::NoResponse, _) => {}
+                (gui::ItemMenuResult::Selected, Some(item)) => {
+                    let player_entity = *self.ecs.fetch::<Entity>();
+                    self.ecs
+                        .write_storage::<WantsToDropItem>()
+                        .insert(player_entity, WantsToDropItem { item })
+                        .expect("Unable to insert intent");
+                    newrunstate = RunState::Running;
+                }
+                _ => newrunstate = RunState::Paused,
+            },
         }
+
+        self.runstate = newrunstate;
     }
 }
 impl State {
+    /// Save the game, logging the reason if that fails. Returns true on success.
+    fn save(&mut self) -> bool {
+        match saveload_system::save_game(&mut self.ecs, self.runstate) {
+            Ok(()) => true,
+            Err(e) => {
+                self.ecs
+                    .write_resource::<GameLog>()
+                    .log(format!("Unable to save: {}", e), RGB::named(rltk::RED));
+                false
+            }
+        }
+    }
+
+    /// Throw away whatever is in the world and generate a fresh dungeon
+    fn start_new_game(&mut self) {
+        let to_delete: Vec<Entity> = self.ecs.entities().join().collect();
+        for del in to_delete.iter() {
+            self.ecs.delete_entity(*del).expect("Deletion failed");
+        }
+        self.ecs.maintain();
+
+        // without an explicit seed, roll one so the run can still be replayed later
+        let seed = self
+            .seed
+            .unwrap_or_else(|| RandomNumberGenerator::new().next_u64());
+        console::log(format!("Seed: {}", seed));
+        self.mapgen_history = new_game(&mut self.ecs, seed, self.builder.as_deref());
+    }
+
+    /// Switch to playing back the level that was just generated if the visualiser is on,
+    /// otherwise carry straight on to `next`
+    fn play_mapgen_then(&mut self, next: RunState) -> RunState {
+        if !self.visualise_mapgen || self.mapgen_history.is_empty() {
+            self.mapgen_history.clear();
+            return next;
+        }
+        self.mapgen_index = 0;
+        self.mapgen_timer = 0.0;
+        RunState::MapGeneration
+    }
+
+    /// Leave the current level for the one `offset` floors below it (negative goes up).
+    /// The level being left is frozen in the master dungeon map so it can be revisited as it was.
+    fn goto_level(&mut self, offset: i32) {
+        freeze_level_entities(&mut self.ecs);
+        let depth = {
+            let map = self.ecs.fetch::<Map>();
+            self.ecs
+                .write_resource::<MasterDungeonMap>()
+                .store_map(&map);
+            map.depth + offset
+        };
+
+        let stored_map = self.ecs.fetch::<MasterDungeonMap>().get_map(depth);
+        let (start_x, start_y) = match stored_map {
+            Some(mut map) => {
+                // arrive on the stairs leading back to where the player came from
+                let arrival = if offset > 0 {
+                    TileType::UpStairs
+                } else {
+                    TileType::DownStairs
+                };
+                let wanted = match map.tiles.iter().position(|tile| *tile == arrival) {
+                    Some(idx) => {
+                        let (x, y) = map.idx_xy(idx);
+                        Point::new(x, y)
+                    }
+                    None => map.start,
+                };
+                thaw_level_entities(&mut self.ecs, depth);
+                let start = free_arrival_tile(&self.ecs, &mut map, wanted);
+                self.ecs.insert(map);
+                (start.x, start.y)
+            }
+            None => {
+                let generated = generate_world_map(&mut self.ecs, depth, self.builder.as_deref());
+                self.mapgen_history = generated.history;
+                generated.start
+            }
+        };
+
+        // move the player onto the new map and make them look around
+        let player_entity = *self.ecs.fetch::<Entity>();
+        *self.ecs.write_resource::<Point>() = Point::new(start_x, start_y);
+        if let Some(pos) = self.ecs.write_storage::<Position>().get_mut(player_entity) {
+            pos.x = start_x;
+            pos.y = start_y;
+        }
+        if let Some(vs) = self.ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+            vs.dirty = true;
+        }
+
+        let verb = if offset > 0 { "descend" } else { "climb" };
+        self.ecs.write_resource::<GameLog>().log(
+            format!("You {} to level {}.", verb, depth),
+            RGB::named(rltk::MAGENTA),
+        );
+    }
+
+    fn queue_item_use(&mut self, item: Entity, target: Option<Point>) {
+        let player_entity = *self.ecs.fetch::<Entity>();
+        self.ecs
+            .write_storage::<WantsToUseItem>()
+            .insert(player_entity, WantsToUseItem { item, target })
+            .expect("Unable to insert intent");
+    }
+
     fn run_systems(&mut self) {
         let mut vis = VisibilitySystem {};
         let mut monster_ai = MonsterAI {};
         let mut map_indexing = MapIndexingSystem {};
+        let mut melee_combat = MeleeCombatSystem {};
+        let mut damage = DamageSystem {};
+        let mut pickup = ItemCollectionSystem {};
+        let mut drop_items = ItemDropSystem {};
+        let mut use_items = ItemUseSystem {};
         vis.run_now(&self.ecs);
         monster_ai.run_now(&self.ecs);
         map_indexing.run_now(&self.ecs);
+        melee_combat.run_now(&self.ecs);
+        use_items.run_now(&self.ecs);
+        damage.run_now(&self.ecs);
+        pickup.run_now(&self.ecs);
+        drop_items.run_now(&self.ecs);
+        self.ecs.write_resource::<GameLog>().advance_turn();
         self.ecs.maintain();
     }
 }
 
 // MAIN
 
+/// Register every component type and the save marker allocator with a fresh world
+fn register_components(ecs: &mut World) {
+    ecs.register::<Player>();
+    ecs.register::<Monster>();
+    ecs.register::<AiState>();
+    ecs.register::<Home>();
+    ecs.register::<Faction>();
+    ecs.register::<Position>();
+    ecs.register::<OtherLevelPosition>();
+    ecs.register::<Renderable>();
+    ecs.register::<Viewshed>();
+    ecs.register::<Map>();
+    ecs.register::<Name>();
+    ecs.register::<BlocksTiles>();
+    ecs.register::<CombatStats>();
+    ecs.register::<WantsToMelee>();
+    ecs.register::<SufferDamage>();
+    ecs.register::<Item>();
+    ecs.register::<InBackpack>();
+    ecs.register::<WantsToPickupItem>();
+    ecs.register::<WantsToDropItem>();
+    ecs.register::<Consumable>();
+    ecs.register::<ProvidesHealing>();
+    ecs.register::<InflictsDamage>();
+    ecs.register::<Ranged>();
+    ecs.register::<AreaOfEffect>();
+    ecs.register::<Confusion>();
+    ecs.register::<WantsToUseItem>();
+    ecs.register::<KilledBy>();
+    ecs.register::<Activity>();
+    ecs.register::<QueuedMovement>();
+    ecs.register::<SimpleMarker<SerializeMe>>();
+    ecs.register::<SerializationHelper>();
+    ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+}
+
+/// What `generate_world_map` reports back about the level it made
+struct GeneratedMap {
+    /// where the player should arrive
+    start: (i32, i32),
+    /// snapshots taken while building, for the visualiser
+    history: Vec<Map>,
+    /// separate walkable regions before unreachable ones were walled off
+    regions: usize,
+}
+
+/// Generate a level at the given depth with the named builder (or a random one),
+/// fill it with monsters and items and make it the current map.
+fn generate_world_map(ecs: &mut World, depth: i32, builder: Option<&str>) -> GeneratedMap {
+    let mut builder = {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let mut builder = match builder {
+            Some(name) => map_builders::builder_by_name(name, MAP_WIDTH, MAP_HEIGHT, depth)
+                .expect("Unknown map builder"),
+            None => map_builders::random_builder(MAP_WIDTH, MAP_HEIGHT, depth, &mut rng),
+        };
+        builder.build_map(&mut rng);
+        builder
+    };
+    builder.spawn_entities(ecs);
+
+    let mut map = builder.get_map();
+    let start = builder.get_starting_position();
+    // builders check connectivity with the strictest rule, so any rule the game uses still works
+    map.corner_cutting = *ecs.fetch::<CornerCutting>();
+
+    map.start = Point::new(start.x, start.y);
+    // below the first floor there is always a way back up where the player arrives
+    if depth > 1 {
+        let start_idx = map.xy_idx(start.x, start.y);
+        map.tiles[start_idx] = TileType::UpStairs;
+    }
+
+    // register the map and move it into ecs
+    ecs.insert(map);
+    GeneratedMap {
+        start: (start.x, start.y),
+        history: builder.get_snapshot_history(),
+        regions: builder.get_region_count(),
+    }
+}
+
+/// Populate an empty world with a freshly generated dungeon, the player and everything in it.
+/// Returns the generation snapshots of the first level.
+fn new_game(ecs: &mut World, seed: u64, builder: Option<&str>) -> Vec<Map> {
+    // every random roll in the game is drawn from this single seeded generator
+    ecs.insert(RandomNumberGenerator::seeded(seed));
+    ecs.insert(MasterDungeonMap::new());
+
+    let generated = generate_world_map(ecs, 1, builder);
+    let (start_x, start_y) = generated.start;
+
+    // create the player where the level wants them to start
+    let player_entity = spawner::player(ecs, start_x, start_y);
+    // register the player's position with ecs
+    ecs.insert(Point::new(start_x, start_y));
+    // register the player entity so systems can target it
+    ecs.insert(player_entity);
+
+    let mut log = GameLog::new();
+    log.log("Welcome to Rust RL", RGB::named(rltk::YELLOW));
+    ecs.insert(log);
+
+    generated.history
+}
+
 fn main() -> rltk::BError {
     use rltk::RltkBuilder;
+    let options = cli::parse_args(std::env::args().skip(1))?;
+    // validation lists every problem on its own line, which BError's Debug output would mangle
+    let raws = raws::RawMaster::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    if options.generate {
+        return Ok(headless::run(&options, &raws)?);
+    }
+
     let context = RltkBuilder::simple80x50()
         .with_fps_cap(60.)
         .with_title("Rust RL")
         .build()?;
     let mut gs = State {
         ecs: World::new(),
-        runstate: RunState::Running,
+        runstate: RunState::MainMenu {
+            selection: gui::MainMenuSelection::NewGame,
+        },
+        seed: options.seed,
+        builder: options.builder,
+        visualise_mapgen: false,
+        mapgen_history: Vec::new(),
+        mapgen_index: 0,
+        mapgen_timer: 0.0,
+        load_failed: false,
     };
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Viewshed>();
-    gs.ecs.register::<Map>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<BlocksTiles>();
-    // generate a Map for placing entities
-    let main_map = Map::new();
-    let mut rng = RandomNumberGenerator::new();
-    let player_spawn_room = rng.range(0, main_map.rooms.len());
-    let (map_center_x, map_center_y) = main_map.rooms[player_spawn_room].center();
-
-    // create the player and place them in the center of a random room
-    gs.ecs
-        .create_entity()
-        .with(Position {
-            x: map_center_x,
-            y: map_center_y,
-        })
-        .with(Renderable {
-            glyph: rltk::to_cp437('@'),
-            fg: RGB::named(rltk::YELLOW),
-            bg: RGB::named(rltk::BLACK),
-        })
-        .with(Viewshed {
-            visible_tiles: Vec::new(),
-            range: 8,
-            dirty: true,
-        })
-        .with(Player {})
-        .with(Name {
-            name: "Player".to_string(),
-        })
-        .with(BlocksTiles {})
-        .build();
-
-    // create an enemy in each room other than the player's
-    let mut rng = RandomNumberGenerator::new();
-    for (i, room) in main_map.rooms.iter().enumerate() {
-        if i != player_spawn_room {
-            let types = ['g', 'o'];
-            let names = ["grim gram", "orca", "orgrimmar", "goob", "gremlin", "osha"];
-            let roll_type = rng.random_slice_entry(&types);
-            let roll_name = rng.random_slice_entry(&names);
-            let mut chosen_type = types[0];
-            let mut chosen_name = names[0];
-            // unpack option from roll_type and assign it to chosen_type if it exists
-            if let Some(t) = roll_type {
-                chosen_type = *t;
-            }
-            // unpack option from roll_name and assign it to chosen_name if it exists
-            if let Some(n) = roll_name {
-                chosen_name = *n;
-            }
-
-            gs.ecs
-                .create_entity()
-                .with(Position {
-                    x: room.center().0,
-                    y: room.center().1,
-                })
-                .with(Renderable {
-                    glyph: rltk::to_cp437(chosen_type),
-                    fg: RGB::named(rltk::RED),
-                    bg: RGB::named(rltk::BLACK),
-                })
-                .with(Viewshed {
-                    dirty: true,
-                    range: 8,
-                    visible_tiles: Vec::new(),
-                })
-                .with(Monster {})
-                .with(Name {
-                    name: chosen_name.to_string(),
-                })
-                .with(BlocksTiles {})
-                .build();
-        }
-    }
+    register_components(&mut gs.ecs);
+    gs.ecs.insert(options.corner_cutting);
+    gs.ecs.insert(Noise::default());
+    gs.ecs.insert(raws);
 
-    // register the map and move it into ecs
-    gs.ecs.insert(main_map);
-    // register the player's position with ecs
-    gs.ecs.insert(Point::new(map_center_x, map_center_y));
+    // window close requests arrive as events so we get a chance to autosave
+    rltk::INPUT.lock().activate_event_queue();
     rltk::main_loop(context, gs)
 }
@@ -1,16 +1,31 @@
-use super::{Player, Rect, Viewshed};
-use rltk::{Algorithm2D, BaseMap, Point, RandomNumberGenerator, Rltk, RGB};
+use super::Rect;
+use rltk::{Algorithm2D, BaseMap, Point};
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use specs_derive::Component;
 use std::cmp::{max, min};
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum TileType {
     Wall,
     Floor,
+    DownStairs,
+    UpStairs,
 }
 
-#[derive(Component)]
+/// When a diagonal step may slip past the walls on either side of it
+#[derive(PartialEq, Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub enum CornerCutting {
+    /// diagonal steps are never stopped by walls
+    Allowed,
+    /// a diagonal step may pass one wall but not squeeze between two
+    NoSqueezing,
+    /// a diagonal step needs both orthogonal neighbours open
+    #[default]
+    Forbidden,
+}
+
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Map {
     pub rooms: Vec<Rect>,
     pub tiles: Vec<TileType>,
@@ -18,12 +33,21 @@ pub struct Map {
     pub visible_tiles: Vec<bool>,
     pub height: i32,
     pub width: i32,
+    /// how many floors below the surface this level sits, starting at 1
+    pub depth: i32,
+    /// where the player first arrived, for when there are no stairs to arrive on
+    pub start: Point,
     pub blocked: Vec<bool>,
+    /// shared by the player's moves and monster pathing so both follow the same rules
+    pub corner_cutting: CornerCutting,
+    /// rebuilt by the map indexing system, so never written to a save
+    #[serde(skip)]
+    pub tile_content: Vec<Vec<Entity>>,
 }
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx as usize] == TileType::Wall
+        self.tiles[idx] == TileType::Wall
     }
 
     fn get_available_exits(&self, idx: usize) -> rltk::SmallVec<[(usize, f32); 10]> {
@@ -43,6 +67,12 @@ impl BaseMap for Map {
             exits.push((self.xy_idx(x, y + 1), 1.0));
         }
 
+        for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            if self.is_exit_valid(x + dx, y + dy) && self.diagonal_step_allowed(x, y, dx, dy) {
+                exits.push((self.xy_idx(x + dx, y + dy), 1.45));
+            }
+        }
+
         exits
     }
 
@@ -61,6 +91,32 @@ impl Algorithm2D for Map {
     }
 }
 
+/// The map as far as the player knows it. Pathing over this never steps onto a tile the
+/// player hasn't seen, so planned routes don't give away the unexplored layout.
+pub struct RevealedMap<'a>(pub &'a Map);
+
+impl BaseMap for RevealedMap<'_> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.0.is_opaque(idx)
+    }
+
+    fn get_available_exits(&self, idx: usize) -> rltk::SmallVec<[(usize, f32); 10]> {
+        let mut exits = self.0.get_available_exits(idx);
+        exits.retain(|(exit, _cost)| self.0.revealed_tiles[*exit]);
+        exits
+    }
+
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.0.get_pathing_distance(idx1, idx2)
+    }
+}
+
+impl Algorithm2D for RevealedMap<'_> {
+    fn dimensions(&self) -> rltk::Point {
+        self.0.dimensions()
+    }
+}
+
 impl Map {
     pub fn xy_idx(&self, x: i32, y: i32) -> usize {
         (y as usize * self.width as usize) + x as usize
@@ -70,193 +126,229 @@ impl Map {
         (idx as i32 % self.width, idx as i32 / self.width)
     }
 
-    /// Makes a map with solid boundaries and 400 randomly placed walls. Just for testing.
-    pub fn new_map_test() -> Map {
-        let mut map = Map {
-            tiles: vec![TileType::Floor; 80 * 50],
+    /// Makes a map of the given size that is solid wall, ready for a builder to carve out
+    pub fn new(width: i32, height: i32, depth: i32) -> Map {
+        let size = (width * height) as usize;
+        Map {
+            tiles: vec![TileType::Wall; size],
             rooms: Vec::new(),
-            revealed_tiles: vec![false; 50 * 80],
-            visible_tiles: vec![false; 50 * 80],
-            blocked: vec![false; 50 * 80],
-            height: 50,
-            width: 80,
-        };
-
-        // make the boundaries of the vector as Wall TileType
-        for x in 0..map.width {
-            // set the top edge to wall
-            let top_index = map.xy_idx(x, 0);
-            map.tiles[top_index] = TileType::Wall;
-            // set the bottom edge to walls
-            let bottom_index = map.xy_idx(x, map.height - 1);
-            map.tiles[bottom_index] = TileType::Wall;
-        }
-        for y in 0..map.height {
-            // set the left edge to walls
-            let left_edge_index = map.xy_idx(0, y);
-            map.tiles[left_edge_index] = TileType::Wall;
-            // set the right edge to walls
-            let right_edge_index = map.xy_idx(map.width - 1, y);
-            map.tiles[right_edge_index] = TileType::Wall;
+            revealed_tiles: vec![false; size],
+            visible_tiles: vec![false; size],
+            start: Point::new(0, 0),
+            blocked: vec![false; size],
+            corner_cutting: CornerCutting::default(),
+            tile_content: vec![Vec::new(); size],
+            height,
+            width,
+            depth,
         }
+    }
 
-        // randomly place walls around the inner part of the map
-        // just for content for now
-        let mut rng = rltk::RandomNumberGenerator::new();
-
-        for _i in 0..400 {
-            let x = rng.roll_dice(1, 79);
-            let y = rng.roll_dice(1, 49);
-            let idx = map.xy_idx(x, y);
-            // place a random wall if the coords are not where the player spawns
-            if idx != map.xy_idx(40, 25) {
-                map.tiles[idx] = TileType::Wall;
-            }
+    fn is_exit_valid(&self, x: i32, y: i32) -> bool {
+        if x < 1 || x >= self.width || y < 1 || y >= self.height {
+            return false;
         }
 
-        map
+        let map_idx = self.xy_idx(x, y);
+        !self.blocked[map_idx]
     }
 
-    fn apply_room_to_map(&mut self, room: &Rect) {
-        for y in room.y1 + 1..=room.y2 {
-            for x in room.x1 + 1..=room.x2 {
-                let room_tile_index = self.xy_idx(x, y);
-                self.tiles[room_tile_index] = TileType::Floor;
-            }
+    fn is_wall(&self, x: i32, y: i32) -> bool {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return true;
         }
+        self.tiles[self.xy_idx(x, y)] == TileType::Wall
     }
 
-    fn apply_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
-        for x in min(x1, x2)..=max(x1, x2) {
-            let idx = self.xy_idx(x, y);
-            let dimensions: usize = (self.width * self.height) as usize;
-            if idx > 0 && idx < dimensions {
-                self.tiles[idx as usize] = TileType::Floor;
-            }
+    /// Whether a step of dx,dy from x,y gets past the corner under this map's corner cutting rule.
+    /// Orthogonal steps always do; whether the destination itself is free is up to the caller.
+    pub fn diagonal_step_allowed(&self, x: i32, y: i32, dx: i32, dy: i32) -> bool {
+        if dx == 0 || dy == 0 {
+            return true;
+        }
+        let wall_beside = self.is_wall(x + dx, y);
+        let wall_below = self.is_wall(x, y + dy);
+        match self.corner_cutting {
+            CornerCutting::Allowed => true,
+            CornerCutting::NoSqueezing => !(wall_beside && wall_below),
+            CornerCutting::Forbidden => !wall_beside && !wall_below,
         }
     }
 
-    fn apply_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32) {
-        for y in min(y1, y2)..=max(y1, y2) {
-            let idx = self.xy_idx(x, y);
-            let dimensions: usize = (self.width * self.height) as usize;
-            if idx > 0 && idx < dimensions {
-                self.tiles[idx as usize] = TileType::Floor;
-            }
+    pub fn populate_blocked(&mut self) {
+        for (i, tile) in self.tiles.iter().enumerate() {
+            self.blocked[i] = *tile == TileType::Wall;
         }
     }
 
-    fn is_exit_valid(&self, x: i32, y: i32) -> bool {
-        if x < 1 || x >= self.width || y < 1 || y >= self.height {
-            return false;
+    pub fn clear_content_index(&mut self) {
+        for content in self.tile_content.iter_mut() {
+            content.clear();
         }
+    }
 
-        let map_idx = self.xy_idx(x, y);
-        !self.blocked[map_idx]
+    /// All entities indexed on the tile at x,y. Empty when the point is off the map.
+    pub fn entities_at(&self, x: i32, y: i32) -> &[Entity] {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return &[];
+        }
+        &self.tile_content[self.xy_idx(x, y)]
     }
 
-    pub fn populate_blocked(&mut self) {
-        for (i, tile) in self.tiles.iter().enumerate() {
-            self.blocked[i] = *tile == TileType::Wall;
+    /// All entities indexed inside the rectangle, edges included, clipped to the map bounds
+    pub fn entities_in_rect(&self, rect: &Rect) -> Vec<Entity> {
+        let mut result = Vec::new();
+        for y in max(rect.y1, 0)..=min(rect.y2, self.height - 1) {
+            for x in max(rect.x1, 0)..=min(rect.x2, self.width - 1) {
+                result.extend_from_slice(&self.tile_content[self.xy_idx(x, y)]);
+            }
         }
+        result
     }
 
-    pub fn new() -> Map {
-        let mut map = Map {
-            tiles: vec![TileType::Wall; 80 * 50],
-            rooms: Vec::new(),
-            revealed_tiles: vec![false; 80 * 50],
-            visible_tiles: vec![false; 80 * 50],
-            blocked: vec![false; 80 * 50],
-            height: 50,
-            width: 80,
-        };
-
-        const MAX_ROOMS: i32 = 30;
-        const MIN_SIZE: i32 = 6;
-        const MAX_SIZE: i32 = 10;
-
-        let mut rng = RandomNumberGenerator::new();
-
-        for _ in 0..MAX_ROOMS {
-            let w = rng.range(MIN_SIZE, MAX_SIZE);
-            let h = rng.range(MIN_SIZE, MAX_SIZE);
-            let x = rng.roll_dice(1, map.width - w - 1) - 1;
-            let y = rng.roll_dice(1, map.height - h - 1) - 1;
-            let new_room = Rect::new(x, y, w, h);
-            let mut ok = true;
-            for other_room in map.rooms.iter() {
-                if new_room.intersect(other_room) {
-                    ok = false
+    /// All entities indexed on tiles whose distance from center is at most radius
+    pub fn entities_in_radius(&self, center: Point, radius: i32) -> Vec<Entity> {
+        let bounds = Rect::new(center.x - radius, center.y - radius, radius * 2, radius * 2);
+        let mut result = Vec::new();
+        for y in max(bounds.y1, 0)..=min(bounds.y2, self.height - 1) {
+            for x in max(bounds.x1, 0)..=min(bounds.x2, self.width - 1) {
+                let distance = rltk::DistanceAlg::Pythagoras.distance2d(center, Point::new(x, y));
+                if distance <= radius as f32 {
+                    result.extend_from_slice(&self.tile_content[self.xy_idx(x, y)]);
                 }
             }
-            if ok {
-                map.apply_room_to_map(&new_room);
-
-                if !map.rooms.is_empty() {
-                    let (new_x, new_y) = new_room.center();
-                    let (prev_x, prev_y) = match map.rooms.last() {
-                        Some(room) => room.center(),
-                        None => (0, 0),
-                    };
-
-                    if rng.roll_dice(1, 2) == 1 {
-                        map.apply_vertical_tunnel(prev_y, new_y, prev_x);
-                        map.apply_horizontal_tunnel(prev_x, new_x, prev_x);
-                    } else {
-                        map.apply_horizontal_tunnel(prev_x, new_x, prev_y);
-                        map.apply_vertical_tunnel(prev_y, new_y, prev_x);
-                    }
-                }
+        }
+        result
+    }
+}
 
-                map.rooms.push(new_room);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5x5 map of floor inside a wall border, with a wall at 2,1
+    fn map_with_corner(corner_cutting: CornerCutting) -> Map {
+        let mut map = Map::new(5, 5, 1);
+        for y in 1..4 {
+            for x in 1..4 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
             }
         }
-
+        let wall = map.xy_idx(2, 1);
+        map.tiles[wall] = TileType::Wall;
+        map.corner_cutting = corner_cutting;
+        map.populate_blocked();
         map
     }
 
-    pub fn draw_map(ecs: &World, ctx: &mut Rltk) {
-        let mut viewsheds = ecs.write_storage::<Viewshed>();
-        let mut players = ecs.write_storage::<Player>();
-        let map = ecs.fetch::<Map>();
-
-        for (_player, _viewshed) in (&mut players, &mut viewsheds).join() {
-            let mut x = 0;
-            let mut y = 0;
-
-            for tile in map.tiles.iter() {
-                let pt = Point { x, y };
-                let xy_to_idx = map.xy_idx(pt.x, pt.y);
-
-                if map.revealed_tiles[xy_to_idx] {
-                    let glyph;
-                    let mut fg;
-                    // map the tile type to a renderable representation
-                    match tile {
-                        TileType::Floor => {
-                            fg = RGB::from_f32(0.0, 0.5, 0.5);
-                            glyph = rltk::to_cp437('.');
-                        }
-                        TileType::Wall => {
-                            fg = RGB::from_f32(0.0, 1.0, 0.0);
-                            glyph = rltk::to_cp437('#');
-                        }
-                    }
-
-                    if !map.visible_tiles[xy_to_idx] {
-                        fg = fg.to_greyscale();
-                    }
-                    ctx.set(x, y, fg, RGB::from_f32(0., 0., 0.), glyph);
-                }
+    #[test]
+    fn corner_cutting_rules_decide_diagonal_exits() {
+        for (rule, expected) in [
+            (CornerCutting::Allowed, true),
+            (CornerCutting::NoSqueezing, true),
+            (CornerCutting::Forbidden, false),
+        ] {
+            let map = map_with_corner(rule);
+            // stepping up-right from 2,2 to 3,1 passes the wall at 2,1
+            assert_eq!(
+                map.diagonal_step_allowed(2, 2, 1, -1),
+                expected,
+                "{:?}",
+                rule
+            );
+            let exits = map.get_available_exits(map.xy_idx(2, 2));
+            let has_exit = exits.iter().any(|(idx, _)| *idx == map.xy_idx(3, 1));
+            assert_eq!(has_exit, expected, "{:?}", rule);
+            // open diagonals cost a little more than a straight step
+            let diagonal = exits.iter().find(|(idx, _)| *idx == map.xy_idx(3, 3));
+            assert_eq!(diagonal.map(|(_, cost)| *cost), Some(1.45));
+        }
+    }
 
-                // Move to the next set of coordinates to draw
-                x += 1;
-                if x > map.width - 1 {
-                    x = 0;
-                    y += 1;
-                }
-            }
+    #[test]
+    fn squeezing_between_two_walls_needs_corner_cutting_allowed() {
+        for (rule, expected) in [
+            (CornerCutting::Allowed, true),
+            (CornerCutting::NoSqueezing, false),
+            (CornerCutting::Forbidden, false),
+        ] {
+            let mut map = map_with_corner(rule);
+            let second_wall = map.xy_idx(1, 2);
+            map.tiles[second_wall] = TileType::Wall;
+            // stepping up-left from 2,2 to 1,1 squeezes between 2,1 and 1,2
+            assert_eq!(
+                map.diagonal_step_allowed(2, 2, -1, -1),
+                expected,
+                "{:?}",
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn revealed_pathing_never_plans_through_unseen_tiles() {
+        let mut map = map_with_corner(CornerCutting::Forbidden);
+        let start = map.xy_idx(1, 1);
+        let goal = map.xy_idx(3, 1);
+        map.revealed_tiles.fill(true);
+        assert!(rltk::a_star_search(start, goal, &RevealedMap(&map)).success);
+
+        // hide the middle row, the only way around the wall at 2,1
+        for x in 1..4 {
+            let idx = map.xy_idx(x, 2);
+            map.revealed_tiles[idx] = false;
+        }
+        assert!(rltk::a_star_search(start, goal, &map).success);
+        assert!(!rltk::a_star_search(start, goal, &RevealedMap(&map)).success);
+    }
+
+    /// A 10x10 map with one fresh entity indexed on each of the given tiles, in order
+    fn map_with_entities(tiles: &[(i32, i32)]) -> (Map, Vec<Entity>) {
+        let mut ecs = World::new();
+        let mut map = Map::new(10, 10, 1);
+        let entities: Vec<Entity> = tiles.iter().map(|_| ecs.create_entity().build()).collect();
+        for ((x, y), entity) in tiles.iter().zip(entities.iter()) {
+            let idx = map.xy_idx(*x, *y);
+            map.tile_content[idx].push(*entity);
         }
+        (map, entities)
+    }
+
+    #[test]
+    fn rect_queries_include_the_edges_and_clip_to_the_map() {
+        let (map, e) = map_with_entities(&[(2, 2), (4, 4), (5, 4), (0, 0), (9, 9)]);
+
+        // 2,2 to 4,4: both corners are inside, 5,4 is just past the right edge
+        let found = map.entities_in_rect(&Rect::new(2, 2, 2, 2));
+        assert_eq!(found, vec![e[0], e[1]]);
+
+        // a rect hanging off every side still finds what is on the map's border
+        let found = map.entities_in_rect(&Rect::new(-5, -5, 20, 20));
+        assert_eq!(found.len(), 5);
+        assert!(found.contains(&e[3]) && found.contains(&e[4]));
+
+        assert!(map.entities_in_rect(&Rect::new(20, 20, 5, 5)).is_empty());
+        assert!(map.entities_at(-1, 0).is_empty());
+        assert_eq!(map.entities_at(9, 9), &[e[4]]);
+    }
+
+    #[test]
+    fn radius_queries_cut_off_the_corners_of_their_bounding_box() {
+        let (map, e) = map_with_entities(&[(5, 5), (7, 5), (5, 3), (7, 7), (6, 7), (9, 5)]);
+
+        let found = map.entities_in_radius(Point::new(5, 5), 2);
+        // the centre and the ends of each axis are in range
+        assert!(found.contains(&e[0]) && found.contains(&e[1]) && found.contains(&e[2]));
+        // 7,7 is a corner of the bounding box, about 2.83 away
+        assert!(!found.contains(&e[3]));
+        // 6,7 is about 2.24 away, also outside
+        assert!(!found.contains(&e[4]));
+        assert!(!found.contains(&e[5]));
+
+        // a circle around a corner of the map is clipped rather than indexing off it
+        let (map, e) = map_with_entities(&[(0, 0), (0, 2), (2, 2)]);
+        let found = map.entities_in_radius(Point::new(0, 0), 2);
+        assert_eq!(found, vec![e[0], e[1]]);
     }
 }
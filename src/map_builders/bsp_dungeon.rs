@@ -0,0 +1,168 @@
+use super::common::{apply_room_to_map, cull_unreachable_regions, draw_corridor};
+use super::{spawner, BuilderState, MapBuilder, Position, Rect, TileType};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+
+/// Rooms placed by repeatedly splitting the map into smaller rectangles, joined in left-to-right order
+pub struct BspDungeonBuilder {
+    state: BuilderState,
+    rects: Vec<Rect>,
+}
+
+impl BspDungeonBuilder {
+    pub fn new(width: i32, height: i32, depth: i32) -> BspDungeonBuilder {
+        BspDungeonBuilder {
+            state: BuilderState::new(width, height, depth),
+            rects: Vec::new(),
+        }
+    }
+
+    fn build_rooms(&mut self, rng: &mut RandomNumberGenerator) {
+        const ATTEMPTS: i32 = 240;
+
+        // start with one rectangle covering the map, less a margin
+        self.rects.clear();
+        self.rects.push(Rect::new(
+            2,
+            2,
+            self.state.map.width - 5,
+            self.state.map.height - 5,
+        ));
+        let first_room = self.rects[0].clone();
+        self.add_subrects(&first_room);
+
+        for _ in 0..ATTEMPTS {
+            let rect = self.random_rect(rng);
+            let candidate = random_sub_rect(&rect, rng);
+
+            if self.is_possible(&candidate) {
+                apply_room_to_map(&mut self.state.map, &candidate);
+                self.take_snapshot();
+                self.state.map.rooms.push(candidate);
+                self.add_subrects(&rect);
+            }
+        }
+
+        // join neighbours from left to right so corridors stay short
+        self.state.map.rooms.sort_by_key(|room| room.x1);
+        for i in 1..self.state.map.rooms.len() {
+            let room = self.state.map.rooms[i - 1].clone();
+            let next_room = self.state.map.rooms[i].clone();
+            // rooms are floored from x1 + 1 to x2, so pick the corridor ends from inside that
+            let start_x = room.x1 + rng.roll_dice(1, room.x2 - room.x1);
+            let start_y = room.y1 + rng.roll_dice(1, room.y2 - room.y1);
+            let end_x = next_room.x1 + rng.roll_dice(1, next_room.x2 - next_room.x1);
+            let end_y = next_room.y1 + rng.roll_dice(1, next_room.y2 - next_room.y1);
+            draw_corridor(&mut self.state.map, start_x, start_y, end_x, end_y);
+            self.take_snapshot();
+        }
+
+        // the way down is in the right-most room
+        if let Some(last_room) = self.state.map.rooms.last() {
+            let (stairs_x, stairs_y) = last_room.center();
+            let stairs_idx = self.state.map.xy_idx(stairs_x, stairs_y);
+            self.state.map.tiles[stairs_idx] = TileType::DownStairs;
+            self.take_snapshot();
+        }
+    }
+
+    /// Split a rectangle into quarters and remember all of them as places rooms could go
+    fn add_subrects(&mut self, rect: &Rect) {
+        let width = i32::abs(rect.x1 - rect.x2);
+        let height = i32::abs(rect.y1 - rect.y2);
+        let half_width = i32::max(width / 2, 1);
+        let half_height = i32::max(height / 2, 1);
+
+        self.rects
+            .push(Rect::new(rect.x1, rect.y1, half_width, half_height));
+        self.rects.push(Rect::new(
+            rect.x1,
+            rect.y1 + half_height,
+            half_width,
+            half_height,
+        ));
+        self.rects.push(Rect::new(
+            rect.x1 + half_width,
+            rect.y1,
+            half_width,
+            half_height,
+        ));
+        self.rects.push(Rect::new(
+            rect.x1 + half_width,
+            rect.y1 + half_height,
+            half_width,
+            half_height,
+        ));
+    }
+
+    fn random_rect(&self, rng: &mut RandomNumberGenerator) -> Rect {
+        let idx = rng.range(0, self.rects.len());
+        self.rects[idx].clone()
+    }
+
+    /// True if the room, plus a wall's width of padding, sits on untouched wall inside the map
+    fn is_possible(&self, room: &Rect) -> bool {
+        let mut expanded = room.clone();
+        expanded.x1 -= 2;
+        expanded.x2 += 2;
+        expanded.y1 -= 2;
+        expanded.y2 += 2;
+
+        for y in expanded.y1..=expanded.y2 {
+            for x in expanded.x1..=expanded.x2 {
+                if x < 1 || x > self.state.map.width - 2 || y < 1 || y > self.state.map.height - 2 {
+                    return false;
+                }
+                let idx = self.state.map.xy_idx(x, y);
+                if self.state.map.tiles[idx] != TileType::Wall {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// A room of at most 10x10 somewhere inside the rectangle
+fn random_sub_rect(rect: &Rect, rng: &mut RandomNumberGenerator) -> Rect {
+    let rect_width = i32::abs(rect.x1 - rect.x2);
+    let rect_height = i32::abs(rect.y1 - rect.y2);
+
+    let w = i32::max(3, rng.roll_dice(1, i32::min(rect_width, 10)) - 1) + 1;
+    let h = i32::max(3, rng.roll_dice(1, i32::min(rect_height, 10)) - 1) + 1;
+    let x = rect.x1 + rng.roll_dice(1, 6) - 1;
+    let y = rect.y1 + rng.roll_dice(1, 6) - 1;
+    Rect::new(x, y, w, h)
+}
+
+impl MapBuilder for BspDungeonBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        // the start and the stairs each need a room of their own
+        self.build_rooms(rng);
+        while self.state.map.rooms.len() < 2 {
+            self.state.restart();
+            self.build_rooms(rng);
+        }
+
+        // corridors should already join every room, but never leave the player somewhere unreachable
+        let (x, y) = self.state.map.rooms[0].center();
+        self.state.starting_position = Position { x, y };
+        let start_idx = self.state.map.xy_idx(x, y);
+        self.state.regions = cull_unreachable_regions(&mut self.state.map, start_idx);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        // the player starts in the first room, so leave it empty
+        for room in self.state.map.rooms.iter().skip(1) {
+            spawner::spawn_room(ecs, &self.state.map, room, self.state.map.depth);
+        }
+    }
+
+    fn state(&self) -> &BuilderState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut BuilderState {
+        &mut self.state
+    }
+}
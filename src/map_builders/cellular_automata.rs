@@ -0,0 +1,124 @@
+use super::common::{
+    cull_unreachable_regions, find_central_floor, generate_voronoi_spawn_regions, has_enough_floor,
+    most_distant_tile, spawn_regions_away_from_start, wall_off_edges,
+};
+use super::{BuilderState, MapBuilder, TileType};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use std::collections::BTreeMap;
+
+/// Natural looking caves grown from random noise by repeatedly smoothing it
+pub struct CellularAutomataBuilder {
+    state: BuilderState,
+    spawn_regions: BTreeMap<usize, Vec<usize>>,
+}
+
+impl CellularAutomataBuilder {
+    pub fn new(width: i32, height: i32, depth: i32) -> CellularAutomataBuilder {
+        CellularAutomataBuilder {
+            state: BuilderState::new(width, height, depth),
+            spawn_regions: BTreeMap::new(),
+        }
+    }
+
+    fn build_caves(&mut self, rng: &mut RandomNumberGenerator) -> bool {
+        const ITERATIONS: i32 = 15;
+
+        // start with roughly 55% floor
+        for y in 1..self.state.map.height - 1 {
+            for x in 1..self.state.map.width - 1 {
+                let idx = self.state.map.xy_idx(x, y);
+                self.state.map.tiles[idx] = if rng.roll_dice(1, 100) > 55 {
+                    TileType::Floor
+                } else {
+                    TileType::Wall
+                };
+            }
+        }
+
+        // a tile becomes wall when crowded by walls or completely isolated
+        for _ in 0..ITERATIONS {
+            let mut new_tiles = self.state.map.tiles.clone();
+            for y in 1..self.state.map.height - 1 {
+                for x in 1..self.state.map.width - 1 {
+                    let mut neighbours = 0;
+                    for (dx, dy) in NEIGHBOURS.iter() {
+                        let idx = self.state.map.xy_idx(x + dx, y + dy);
+                        if self.state.map.tiles[idx] == TileType::Wall {
+                            neighbours += 1;
+                        }
+                    }
+
+                    let idx = self.state.map.xy_idx(x, y);
+                    new_tiles[idx] = if neighbours > 4 || neighbours == 0 {
+                        TileType::Wall
+                    } else {
+                        TileType::Floor
+                    };
+                }
+            }
+            self.state.map.tiles = new_tiles;
+            self.take_snapshot();
+        }
+        wall_off_edges(&mut self.state.map);
+
+        let Some(start) = find_central_floor(&self.state.map) else {
+            return false;
+        };
+        self.state.starting_position = start;
+        let start_idx = self.state.map.xy_idx(
+            self.state.starting_position.x,
+            self.state.starting_position.y,
+        );
+        self.state.regions = cull_unreachable_regions(&mut self.state.map, start_idx);
+        // starting in a walled-in pocket culls away everything else
+        if !has_enough_floor(&self.state.map) {
+            return false;
+        }
+        let Some(exit_idx) = most_distant_tile(&mut self.state.map, start_idx) else {
+            return false;
+        };
+        self.state.map.tiles[exit_idx] = TileType::DownStairs;
+        self.take_snapshot();
+
+        self.spawn_regions = generate_voronoi_spawn_regions(&self.state.map, rng);
+        true
+    }
+}
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        // the noise can smooth away to a cave too small for a start and the stairs
+        while !self.build_caves(rng) {
+            self.state.restart();
+        }
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        spawn_regions_away_from_start(
+            ecs,
+            &self.state.map,
+            &self.spawn_regions,
+            &self.state.starting_position,
+        );
+    }
+
+    fn state(&self) -> &BuilderState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut BuilderState {
+        &mut self.state
+    }
+}
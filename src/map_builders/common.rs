@@ -0,0 +1,276 @@
+use super::{spawner, Map, Position, Rect, TileType};
+use rltk::BaseMap;
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+
+/// A copy of the map as generation currently stands, fully revealed so every tile can be drawn
+pub fn snapshot(map: &Map) -> Map {
+    let mut snapshot = map.clone();
+    snapshot.revealed_tiles.fill(true);
+    snapshot.visible_tiles.fill(true);
+    // nothing stands on a map that is still being built
+    snapshot.tile_content = Vec::new();
+    snapshot
+}
+
+pub fn apply_room_to_map(map: &mut Map, room: &Rect) {
+    for y in room.y1 + 1..=room.y2 {
+        for x in room.x1 + 1..=room.x2 {
+            let room_tile_index = map.xy_idx(x, y);
+            map.tiles[room_tile_index] = TileType::Floor;
+        }
+    }
+}
+
+pub fn apply_horizontal_tunnel(map: &mut Map, x1: i32, x2: i32, y: i32) {
+    for x in min(x1, x2)..=max(x1, x2) {
+        let idx = map.xy_idx(x, y);
+        let dimensions: usize = (map.width * map.height) as usize;
+        if idx > 0 && idx < dimensions {
+            map.tiles[idx] = TileType::Floor;
+        }
+    }
+}
+
+pub fn apply_vertical_tunnel(map: &mut Map, y1: i32, y2: i32, x: i32) {
+    for y in min(y1, y2)..=max(y1, y2) {
+        let idx = map.xy_idx(x, y);
+        let dimensions: usize = (map.width * map.height) as usize;
+        if idx > 0 && idx < dimensions {
+            map.tiles[idx] = TileType::Floor;
+        }
+    }
+}
+
+/// Carve a dog-leg corridor from one point to another, one step at a time
+pub fn draw_corridor(map: &mut Map, x1: i32, y1: i32, x2: i32, y2: i32) {
+    let mut x = x1;
+    let mut y = y1;
+
+    while x != x2 || y != y2 {
+        if x < x2 {
+            x += 1;
+        } else if x > x2 {
+            x -= 1;
+        } else if y < y2 {
+            y += 1;
+        } else if y > y2 {
+            y -= 1;
+        }
+
+        let idx = map.xy_idx(x, y);
+        map.tiles[idx] = TileType::Floor;
+    }
+}
+
+/// Turn the outermost ring of tiles back into wall so nothing can walk off the map
+pub fn wall_off_edges(map: &mut Map) {
+    for x in 0..map.width {
+        let top = map.xy_idx(x, 0);
+        let bottom = map.xy_idx(x, map.height - 1);
+        map.tiles[top] = TileType::Wall;
+        map.tiles[bottom] = TileType::Wall;
+    }
+    for y in 0..map.height {
+        let left = map.xy_idx(0, y);
+        let right = map.xy_idx(map.width - 1, y);
+        map.tiles[left] = TileType::Wall;
+        map.tiles[right] = TileType::Wall;
+    }
+}
+
+/// The floor tile closest to the middle of the map, searching leftwards along each row.
+/// `None` when the map has no floor at all.
+pub fn find_central_floor(map: &Map) -> Option<Position> {
+    let centre = map.xy_idx(map.width / 2, map.height / 2);
+    (0..map.tiles.len())
+        .map(|offset| (centre + map.tiles.len() - offset) % map.tiles.len())
+        .find(|idx| map.tiles[*idx] == TileType::Floor)
+        .map(|idx| {
+            let (x, y) = map.idx_xy(idx);
+            Position { x, y }
+        })
+}
+
+/// Mark every tile reachable from `start_idx` by following the map's exits
+fn flood_fill(map: &Map, start_idx: usize, reached: &mut [bool]) {
+    let mut open = vec![start_idx];
+    reached[start_idx] = true;
+    while let Some(idx) = open.pop() {
+        for (exit, _cost) in map.get_available_exits(idx) {
+            if !reached[exit] {
+                reached[exit] = true;
+                open.push(exit);
+            }
+        }
+    }
+}
+
+/// Count the separate walkable regions of the map, then wall over every one that can't be
+/// walked to from `start_idx`. Returns the number of regions there were before culling.
+pub fn cull_unreachable_regions(map: &mut Map, start_idx: usize) -> usize {
+    map.populate_blocked();
+
+    let mut reachable = vec![false; map.tiles.len()];
+    flood_fill(map, start_idx, &mut reachable);
+
+    let mut region_count = 1;
+    let mut seen = reachable.clone();
+    for idx in 0..map.tiles.len() {
+        if !map.blocked[idx] && !seen[idx] {
+            flood_fill(map, idx, &mut seen);
+            region_count += 1;
+        }
+    }
+
+    for (tile, reached) in map.tiles.iter_mut().zip(reachable.iter()) {
+        if !*reached {
+            *tile = TileType::Wall;
+        }
+    }
+    map.populate_blocked();
+
+    region_count
+}
+
+/// The smallest share of the map, in percent, a level may be left with once it has been culled
+pub const MIN_FLOOR_PERCENT: usize = 10;
+
+/// Whether enough of the map is still walkable to be worth playing
+pub fn has_enough_floor(map: &Map) -> bool {
+    let floor = map
+        .tiles
+        .iter()
+        .filter(|tile| **tile != TileType::Wall)
+        .count();
+    floor * 100 >= map.tiles.len() * MIN_FLOOR_PERCENT
+}
+
+/// The walkable tile that is furthest from `start_idx`, which is where the stairs belong.
+/// `None` when nothing but the start itself can be reached.
+pub fn most_distant_tile(map: &mut Map, start_idx: usize) -> Option<usize> {
+    map.populate_blocked();
+    let dijkstra_map = rltk::DijkstraMap::new(
+        map.width as usize,
+        map.height as usize,
+        &[start_idx],
+        &*map,
+        (map.width * map.height) as f32,
+    );
+
+    let mut exit_tile = None;
+    let mut furthest = 0.0f32;
+    for (i, distance_to_start) in dijkstra_map.map.iter().enumerate() {
+        // rltk never zeroes the start itself, so it can pick up the cost of a round trip
+        if i != start_idx
+            && !map.blocked[i]
+            && *distance_to_start != f32::MAX
+            && *distance_to_start > furthest
+        {
+            exit_tile = Some(i);
+            furthest = *distance_to_start;
+        }
+    }
+    exit_tile
+}
+
+/// Split the floor into roughly even patches around random seed points so
+/// levels without rooms still get their monsters and items spread out
+pub fn generate_voronoi_spawn_regions(
+    map: &Map,
+    rng: &mut RandomNumberGenerator,
+) -> BTreeMap<usize, Vec<usize>> {
+    const SEEDS: usize = 32;
+    let seeds: Vec<rltk::Point> = (0..SEEDS)
+        .map(|_| rltk::Point::new(rng.range(1, map.width - 1), rng.range(1, map.height - 1)))
+        .collect();
+
+    let mut regions: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (idx, tile) in map.tiles.iter().enumerate() {
+        if *tile != TileType::Floor {
+            continue;
+        }
+        let (x, y) = map.idx_xy(idx);
+        let here = rltk::Point::new(x, y);
+        let nearest_seed = seeds
+            .iter()
+            .enumerate()
+            .map(|(i, seed)| {
+                (
+                    i,
+                    rltk::DistanceAlg::PythagorasSquared.distance2d(here, *seed),
+                )
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+            .expect("There are no voronoi seeds");
+        regions.entry(nearest_seed).or_default().push(idx);
+    }
+
+    regions
+}
+
+/// Populate each spawn region except the one the player starts in
+pub fn spawn_regions_away_from_start(
+    ecs: &mut World,
+    map: &Map,
+    regions: &BTreeMap<usize, Vec<usize>>,
+    start: &Position,
+) {
+    let start_idx = map.xy_idx(start.x, start.y);
+    for area in regions.values() {
+        if !area.contains(&start_idx) {
+            spawner::spawn_region(ecs, map, area, map.depth);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn culling_keeps_only_the_region_holding_the_start() {
+        let mut map = Map::new(20, 10, 1);
+        apply_room_to_map(&mut map, &Rect::new(1, 1, 4, 4));
+        apply_room_to_map(&mut map, &Rect::new(10, 1, 4, 4));
+        let start_idx = map.xy_idx(3, 3);
+        let cut_off_idx = map.xy_idx(12, 3);
+
+        assert_eq!(cull_unreachable_regions(&mut map, start_idx), 2);
+        assert!(map.tiles[start_idx] == TileType::Floor);
+        assert!(map.tiles[cut_off_idx] == TileType::Wall);
+        assert_eq!(cull_unreachable_regions(&mut map, start_idx), 1);
+    }
+
+    #[test]
+    fn a_tunnel_joins_two_rooms_into_one_region() {
+        let mut map = Map::new(20, 10, 1);
+        apply_room_to_map(&mut map, &Rect::new(1, 1, 4, 4));
+        apply_room_to_map(&mut map, &Rect::new(10, 1, 4, 4));
+        apply_horizontal_tunnel(&mut map, 3, 12, 3);
+
+        let start_idx = map.xy_idx(3, 3);
+        assert_eq!(cull_unreachable_regions(&mut map, start_idx), 1);
+    }
+
+    #[test]
+    fn a_map_without_floor_has_no_centre() {
+        let map = Map::new(20, 10, 1);
+        assert!(find_central_floor(&map).is_none());
+    }
+
+    #[test]
+    fn a_lone_floor_tile_has_nowhere_for_the_stairs() {
+        let mut map = Map::new(20, 10, 1);
+        let start_idx = map.xy_idx(5, 5);
+        map.tiles[start_idx] = TileType::Floor;
+        assert_eq!(most_distant_tile(&mut map, start_idx), None);
+
+        let next_idx = map.xy_idx(6, 5);
+        map.tiles[next_idx] = TileType::Floor;
+        assert_eq!(most_distant_tile(&mut map, start_idx), Some(next_idx));
+    }
+}
@@ -0,0 +1,116 @@
+use super::common::{
+    cull_unreachable_regions, generate_voronoi_spawn_regions, most_distant_tile,
+    spawn_regions_away_from_start,
+};
+use super::{BuilderState, MapBuilder, Position, TileType};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use std::collections::BTreeMap;
+
+/// Winding open areas dug by wanderers stumbling about until half the map is floor
+pub struct DrunkardsWalkBuilder {
+    state: BuilderState,
+    spawn_regions: BTreeMap<usize, Vec<usize>>,
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new(width: i32, height: i32, depth: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder {
+            state: BuilderState::new(width, height, depth),
+            spawn_regions: BTreeMap::new(),
+        }
+    }
+
+    fn dig(&mut self, rng: &mut RandomNumberGenerator) -> bool {
+        const DRUNKARD_LIFETIME: i32 = 400;
+        const DESIRED_FLOOR_PERCENT: usize = 50;
+
+        self.state.starting_position = Position {
+            x: self.state.map.width / 2,
+            y: self.state.map.height / 2,
+        };
+        let start_idx = self.state.map.xy_idx(
+            self.state.starting_position.x,
+            self.state.starting_position.y,
+        );
+        self.state.map.tiles[start_idx] = TileType::Floor;
+
+        let desired_floor_tiles = self.state.map.tiles.len() * DESIRED_FLOOR_PERCENT / 100;
+        let mut floor_tile_count = 1;
+        let mut digger_count = 0;
+        while floor_tile_count < desired_floor_tiles {
+            // the first drunkard sets off from the start, the rest from anywhere already dug
+            let (mut x, mut y) = if digger_count == 0 {
+                (
+                    self.state.starting_position.x,
+                    self.state.starting_position.y,
+                )
+            } else {
+                let floor: Vec<usize> = self
+                    .state
+                    .map
+                    .tiles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, tile)| **tile == TileType::Floor)
+                    .map(|(idx, _)| idx)
+                    .collect();
+                self.state.map.idx_xy(floor[rng.range(0, floor.len())])
+            };
+
+            for _ in 0..DRUNKARD_LIFETIME {
+                let idx = self.state.map.xy_idx(x, y);
+                if self.state.map.tiles[idx] == TileType::Wall {
+                    self.state.map.tiles[idx] = TileType::Floor;
+                    floor_tile_count += 1;
+                }
+
+                match rng.roll_dice(1, 4) {
+                    1 if x > 2 => x -= 1,
+                    2 if x < self.state.map.width - 3 => x += 1,
+                    3 if y > 2 => y -= 1,
+                    4 if y < self.state.map.height - 3 => y += 1,
+                    _ => {}
+                }
+            }
+            digger_count += 1;
+            self.take_snapshot();
+        }
+
+        self.state.regions = cull_unreachable_regions(&mut self.state.map, start_idx);
+        let Some(exit_idx) = most_distant_tile(&mut self.state.map, start_idx) else {
+            return false;
+        };
+        self.state.map.tiles[exit_idx] = TileType::DownStairs;
+        self.take_snapshot();
+
+        self.spawn_regions = generate_voronoi_spawn_regions(&self.state.map, rng);
+        true
+    }
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        // the stairs need somewhere to go other than the start
+        while !self.dig(rng) {
+            self.state.restart();
+        }
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        spawn_regions_away_from_start(
+            ecs,
+            &self.state.map,
+            &self.spawn_regions,
+            &self.state.starting_position,
+        );
+    }
+
+    fn state(&self) -> &BuilderState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut BuilderState {
+        &mut self.state
+    }
+}
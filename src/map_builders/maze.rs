@@ -0,0 +1,107 @@
+use super::common::{
+    cull_unreachable_regions, generate_voronoi_spawn_regions, most_distant_tile,
+    spawn_regions_away_from_start,
+};
+use super::{BuilderState, MapBuilder, Position, TileType};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use std::collections::BTreeMap;
+
+/// A perfect maze of one-tile corridors carved by a recursive backtracker
+pub struct MazeBuilder {
+    state: BuilderState,
+    spawn_regions: BTreeMap<usize, Vec<usize>>,
+}
+
+impl MazeBuilder {
+    pub fn new(width: i32, height: i32, depth: i32) -> MazeBuilder {
+        MazeBuilder {
+            state: BuilderState::new(width, height, depth),
+            spawn_regions: BTreeMap::new(),
+        }
+    }
+
+    fn carve_maze(&mut self, rng: &mut RandomNumberGenerator) {
+        // maze cells sit on odd tiles, with the even tiles between them left as wall or knocked through
+        let cells_wide = (self.state.map.width - 2) / 2;
+        let cells_high = (self.state.map.height - 2) / 2;
+        let cell_to_tile = |cx: i32, cy: i32| (cx * 2 + 1, cy * 2 + 1);
+
+        let mut visited = vec![false; (cells_wide * cells_high) as usize];
+        let mut stack: Vec<(i32, i32)> = vec![(0, 0)];
+        visited[0] = true;
+        let (x, y) = cell_to_tile(0, 0);
+        self.state.starting_position = Position { x, y };
+        let start_idx = self.state.map.xy_idx(x, y);
+        self.state.map.tiles[start_idx] = TileType::Floor;
+
+        let mut carved = 0;
+        while let Some(&(cx, cy)) = stack.last() {
+            let unvisited: Vec<(i32, i32)> = [(0, -1), (0, 1), (-1, 0), (1, 0)]
+                .iter()
+                .map(|(dx, dy)| (cx + dx, cy + dy))
+                .filter(|(nx, ny)| {
+                    *nx >= 0
+                        && *nx < cells_wide
+                        && *ny >= 0
+                        && *ny < cells_high
+                        && !visited[(ny * cells_wide + nx) as usize]
+                })
+                .collect();
+
+            if unvisited.is_empty() {
+                stack.pop();
+                continue;
+            }
+
+            let (nx, ny) = unvisited[rng.range(0, unvisited.len())];
+            visited[(ny * cells_wide + nx) as usize] = true;
+
+            // knock through the wall between the cells and open up the new one
+            let (x, y) = cell_to_tile(cx, cy);
+            let wall_idx = self.state.map.xy_idx(x + (nx - cx), y + (ny - cy));
+            self.state.map.tiles[wall_idx] = TileType::Floor;
+            let (x, y) = cell_to_tile(nx, ny);
+            let cell_idx = self.state.map.xy_idx(x, y);
+            self.state.map.tiles[cell_idx] = TileType::Floor;
+
+            stack.push((nx, ny));
+            carved += 1;
+            if carved % 50 == 0 {
+                self.take_snapshot();
+            }
+        }
+
+        self.state.regions = cull_unreachable_regions(&mut self.state.map, start_idx);
+        // every cell is carved into the maze, so only a single-cell map has nowhere for the stairs
+        let exit_idx = most_distant_tile(&mut self.state.map, start_idx)
+            .expect("The map is too small for a maze with stairs");
+        self.state.map.tiles[exit_idx] = TileType::DownStairs;
+        self.take_snapshot();
+
+        self.spawn_regions = generate_voronoi_spawn_regions(&self.state.map, rng);
+    }
+}
+
+impl MapBuilder for MazeBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.carve_maze(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        spawn_regions_away_from_start(
+            ecs,
+            &self.state.map,
+            &self.spawn_regions,
+            &self.state.starting_position,
+        );
+    }
+
+    fn state(&self) -> &BuilderState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut BuilderState {
+        &mut self.state
+    }
+}
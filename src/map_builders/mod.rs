@@ -0,0 +1,291 @@
+use super::{spawner, Map, Position, Rect, TileType};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+mod bsp_dungeon;
+mod cellular_automata;
+mod common;
+mod drunkard;
+mod maze;
+mod simple_map;
+mod voronoi;
+use bsp_dungeon::BspDungeonBuilder;
+use cellular_automata::CellularAutomataBuilder;
+use drunkard::DrunkardsWalkBuilder;
+use maze::MazeBuilder;
+use simple_map::SimpleMapBuilder;
+use voronoi::VoronoiBuilder;
+
+/// What every builder keeps track of while it works, behind the `MapBuilder` default methods
+pub struct BuilderState {
+    pub map: Map,
+    pub history: Vec<Map>,
+    /// separate walkable regions before unreachable ones were walled off
+    pub regions: usize,
+    pub starting_position: Position,
+}
+
+impl BuilderState {
+    pub fn new(width: i32, height: i32, depth: i32) -> BuilderState {
+        BuilderState {
+            map: Map::new(width, height, depth),
+            history: Vec::new(),
+            regions: 0,
+            starting_position: Position { x: 0, y: 0 },
+        }
+    }
+
+    /// Throw away a failed attempt, snapshots included, so playback only shows the level that was kept
+    pub fn restart(&mut self) {
+        *self = BuilderState::new(self.map.width, self.map.height, self.map.depth);
+    }
+}
+
+/// Something that can carve out a level and then populate it
+pub trait MapBuilder {
+    /// Generate the level, drawing every roll from the supplied rng
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator);
+    /// Fill the generated level with monsters and items
+    fn spawn_entities(&mut self, ecs: &mut World);
+    fn state(&self) -> &BuilderState;
+    fn state_mut(&mut self) -> &mut BuilderState;
+
+    fn get_map(&self) -> Map {
+        self.state().map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.state().starting_position.clone()
+    }
+
+    /// How many separate walkable regions the level had before unreachable ones were walled off
+    fn get_region_count(&self) -> usize {
+        self.state().regions
+    }
+
+    /// Every snapshot taken while building, oldest first
+    fn get_snapshot_history(&self) -> Vec<Map> {
+        self.state().history.clone()
+    }
+
+    /// Record the map as it currently stands so generation can be played back
+    fn take_snapshot(&mut self) {
+        let state = self.state_mut();
+        let snapshot = common::snapshot(&state.map);
+        state.history.push(snapshot);
+    }
+}
+
+/// Every builder that can be asked for by name, in the order they are listed to the user
+pub const BUILDER_NAMES: [&str; 6] = ["simple", "bsp", "cellular", "drunkard", "maze", "voronoi"];
+
+/// The builder registered under `name`, or None if there is no such builder
+pub fn builder_by_name(
+    name: &str,
+    width: i32,
+    height: i32,
+    depth: i32,
+) -> Option<Box<dyn MapBuilder>> {
+    let builder: Box<dyn MapBuilder> = match name {
+        "simple" => Box::new(SimpleMapBuilder::new(width, height, depth)),
+        "bsp" => Box::new(BspDungeonBuilder::new(width, height, depth)),
+        "cellular" => Box::new(CellularAutomataBuilder::new(width, height, depth)),
+        "drunkard" => Box::new(DrunkardsWalkBuilder::new(width, height, depth)),
+        "maze" => Box::new(MazeBuilder::new(width, height, depth)),
+        "voronoi" => Box::new(VoronoiBuilder::new(width, height, depth)),
+        _ => return None,
+    };
+    Some(builder)
+}
+
+/// The name of any one of the builders, chosen at random
+pub fn random_builder_name(rng: &mut RandomNumberGenerator) -> &'static str {
+    rng.random_slice_entry(&BUILDER_NAMES)
+        .expect("There are no map builders")
+}
+
+/// Any one of the builders, chosen at random
+pub fn random_builder(
+    width: i32,
+    height: i32,
+    depth: i32,
+    rng: &mut RandomNumberGenerator,
+) -> Box<dyn MapBuilder> {
+    builder_by_name(random_builder_name(rng), width, height, depth)
+        .expect("Unknown builder in BUILDER_NAMES")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: u64 = 40;
+
+    fn build(name: &str, seed: u64) -> Box<dyn MapBuilder> {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let mut builder = builder_by_name(name, 80, 50, 1).expect("Unknown builder");
+        builder.build_map(&mut rng);
+        builder
+    }
+
+    #[test]
+    fn every_builder_leaves_one_region_reachable_from_the_start() {
+        for name in BUILDER_NAMES.iter() {
+            for seed in 0..SEEDS {
+                let builder = build(name, seed);
+                let mut map = builder.get_map();
+                let start = builder.get_starting_position();
+                let start_idx = map.xy_idx(start.x, start.y);
+
+                assert!(
+                    map.tiles[start_idx] == TileType::Floor,
+                    "{} seed {} starts the player in a wall",
+                    name,
+                    seed
+                );
+                assert!(
+                    map.tiles.contains(&TileType::DownStairs),
+                    "{} seed {} has no way down",
+                    name,
+                    seed
+                );
+                assert_eq!(
+                    common::cull_unreachable_regions(&mut map, start_idx),
+                    1,
+                    "{} seed {} left unreachable floor behind",
+                    name,
+                    seed
+                );
+                assert!(
+                    common::has_enough_floor(&map),
+                    "{} seed {} kept less than {}% of the map as floor",
+                    name,
+                    seed,
+                    common::MIN_FLOOR_PERCENT
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn retried_attempts_are_left_out_of_the_playback() {
+        for seed in 0..SEEDS {
+            // voronoi snapshots once after carving and once after placing the stairs
+            let builder = build("voronoi", seed);
+            assert_eq!(
+                builder.get_snapshot_history().len(),
+                2,
+                "voronoi seed {} played back a failed attempt",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn room_builders_connect_every_room() {
+        for name in ["simple", "bsp"].iter() {
+            for seed in 0..SEEDS {
+                let builder = build(name, seed);
+                assert_eq!(
+                    builder.get_region_count(),
+                    1,
+                    "{} seed {} generated disconnected rooms",
+                    name,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cramped_room_builders_keep_the_start_off_the_stairs() {
+        for name in ["simple", "bsp"].iter() {
+            for seed in 0..SEEDS {
+                // small enough that a single room is a common first attempt
+                let mut rng = RandomNumberGenerator::seeded(seed);
+                let mut builder = builder_by_name(name, 26, 20, 2).expect("Unknown builder");
+                builder.build_map(&mut rng);
+                let map = builder.get_map();
+                let start = builder.get_starting_position();
+
+                assert!(
+                    map.rooms.len() >= 2,
+                    "{} seed {} has only one room",
+                    name,
+                    seed
+                );
+                assert!(
+                    map.tiles[map.xy_idx(start.x, start.y)] == TileType::Floor,
+                    "{} seed {} starts the player on the stairs",
+                    name,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn spawns_get_their_own_floor_tile_and_leave_the_level_connected() {
+        for name in BUILDER_NAMES.iter() {
+            for seed in 0..SEEDS / 4 {
+                let mut ecs = World::new();
+                crate::register_components(&mut ecs);
+                ecs.insert(crate::raws::RawMaster::embedded().expect("Built-in raws are valid"));
+                ecs.insert(RandomNumberGenerator::seeded(seed));
+
+                let mut builder = build(name, seed);
+                builder.spawn_entities(&mut ecs);
+                let mut map = builder.get_map();
+                let start = builder.get_starting_position();
+
+                let positions = ecs.read_storage::<Position>();
+                let blockers = ecs.read_storage::<crate::BlocksTiles>();
+                let monsters = ecs.read_storage::<crate::Monster>();
+                let mut taken = std::collections::HashSet::new();
+                let mut props = Vec::new();
+                for (pos, blocks, monster) in
+                    (&positions, blockers.maybe(), monsters.maybe()).join()
+                {
+                    let idx = map.xy_idx(pos.x, pos.y);
+                    assert!(
+                        map.tiles[idx] == TileType::Floor,
+                        "{} seed {} spawned something off the floor at {},{}",
+                        name,
+                        seed,
+                        pos.x,
+                        pos.y
+                    );
+                    assert!(
+                        (pos.x, pos.y) != (start.x, start.y),
+                        "{} seed {} spawned something on the player",
+                        name,
+                        seed
+                    );
+                    assert!(
+                        taken.insert(idx),
+                        "{} seed {} spawned two things on {},{}",
+                        name,
+                        seed,
+                        pos.x,
+                        pos.y
+                    );
+                    if blocks.is_some() && monster.is_none() {
+                        props.push(idx);
+                    }
+                }
+
+                // blocking props never move, so treat them as walls
+                for idx in props {
+                    map.tiles[idx] = TileType::Wall;
+                }
+                let start_idx = map.xy_idx(start.x, start.y);
+                assert_eq!(
+                    common::cull_unreachable_regions(&mut map, start_idx),
+                    1,
+                    "{} seed {} was cut in two by props",
+                    name,
+                    seed
+                );
+            }
+        }
+    }
+}
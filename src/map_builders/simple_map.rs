@@ -0,0 +1,114 @@
+use super::common::{
+    apply_horizontal_tunnel, apply_room_to_map, apply_vertical_tunnel, cull_unreachable_regions,
+};
+use super::{spawner, BuilderState, MapBuilder, Position, Rect, TileType};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+
+/// Rectangular rooms scattered at random, each joined to the one before it by an L-shaped corridor
+pub struct SimpleMapBuilder {
+    state: BuilderState,
+    starting_room: usize,
+}
+
+impl SimpleMapBuilder {
+    pub fn new(width: i32, height: i32, depth: i32) -> SimpleMapBuilder {
+        SimpleMapBuilder {
+            state: BuilderState::new(width, height, depth),
+            starting_room: 0,
+        }
+    }
+
+    fn rooms_and_corridors(&mut self, rng: &mut RandomNumberGenerator) {
+        const MAX_ROOMS: i32 = 30;
+        const MIN_SIZE: i32 = 6;
+        const MAX_SIZE: i32 = 10;
+
+        for _ in 0..MAX_ROOMS {
+            let w = rng.range(MIN_SIZE, MAX_SIZE);
+            let h = rng.range(MIN_SIZE, MAX_SIZE);
+            let x = rng.roll_dice(1, self.state.map.width - w - 1) - 1;
+            let y = rng.roll_dice(1, self.state.map.height - h - 1) - 1;
+            let new_room = Rect::new(x, y, w, h);
+            let mut ok = true;
+            for other_room in self.state.map.rooms.iter() {
+                if new_room.intersect(other_room) {
+                    ok = false
+                }
+            }
+            if ok {
+                apply_room_to_map(&mut self.state.map, &new_room);
+                self.take_snapshot();
+
+                if !self.state.map.rooms.is_empty() {
+                    let (new_x, new_y) = new_room.center();
+                    let (prev_x, prev_y) = match self.state.map.rooms.last() {
+                        Some(room) => room.center(),
+                        None => (0, 0),
+                    };
+
+                    if rng.roll_dice(1, 2) == 1 {
+                        apply_vertical_tunnel(&mut self.state.map, prev_y, new_y, prev_x);
+                        self.take_snapshot();
+                        apply_horizontal_tunnel(&mut self.state.map, prev_x, new_x, new_y);
+                        self.take_snapshot();
+                    } else {
+                        apply_horizontal_tunnel(&mut self.state.map, prev_x, new_x, prev_y);
+                        self.take_snapshot();
+                        apply_vertical_tunnel(&mut self.state.map, prev_y, new_y, new_x);
+                        self.take_snapshot();
+                    }
+                }
+
+                self.state.map.rooms.push(new_room);
+            }
+        }
+
+        // the way down is in the last room carved
+        if let Some(last_room) = self.state.map.rooms.last() {
+            let (stairs_x, stairs_y) = last_room.center();
+            let stairs_idx = self.state.map.xy_idx(stairs_x, stairs_y);
+            self.state.map.tiles[stairs_idx] = TileType::DownStairs;
+            self.take_snapshot();
+        }
+
+        // the last room holds the down stairs, so never start the player there
+        if self.state.map.rooms.len() > 1 {
+            self.starting_room = rng.range(0, self.state.map.rooms.len() - 1);
+        }
+    }
+}
+
+impl MapBuilder for SimpleMapBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        // the start and the stairs each need a room of their own
+        self.rooms_and_corridors(rng);
+        while self.state.map.rooms.len() < 2 {
+            self.state.restart();
+            self.rooms_and_corridors(rng);
+        }
+
+        // corridors should already join every room, but never leave the player somewhere unreachable
+        let (x, y) = self.state.map.rooms[self.starting_room].center();
+        self.state.starting_position = Position { x, y };
+        let start_idx = self.state.map.xy_idx(x, y);
+        self.state.regions = cull_unreachable_regions(&mut self.state.map, start_idx);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        // the player's room starts out empty
+        for (i, room) in self.state.map.rooms.iter().enumerate() {
+            if i != self.starting_room {
+                spawner::spawn_room(ecs, &self.state.map, room, self.state.map.depth);
+            }
+        }
+    }
+
+    fn state(&self) -> &BuilderState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut BuilderState {
+        &mut self.state
+    }
+}
@@ -0,0 +1,124 @@
+use super::common::{
+    cull_unreachable_regions, find_central_floor, generate_voronoi_spawn_regions, has_enough_floor,
+    most_distant_tile, spawn_regions_away_from_start, wall_off_edges,
+};
+use super::{BuilderState, MapBuilder, TileType};
+use rltk::{DistanceAlg, Point, RandomNumberGenerator};
+use specs::prelude::*;
+use std::collections::BTreeMap;
+
+/// Irregular cells around random seed points, with walls where neighbouring cells meet
+pub struct VoronoiBuilder {
+    state: BuilderState,
+    spawn_regions: BTreeMap<usize, Vec<usize>>,
+}
+
+impl VoronoiBuilder {
+    pub fn new(width: i32, height: i32, depth: i32) -> VoronoiBuilder {
+        VoronoiBuilder {
+            state: BuilderState::new(width, height, depth),
+            spawn_regions: BTreeMap::new(),
+        }
+    }
+
+    fn build_cells(&mut self, rng: &mut RandomNumberGenerator) -> bool {
+        const SEEDS: usize = 64;
+
+        let seeds: Vec<Point> = (0..SEEDS)
+            .map(|_| {
+                Point::new(
+                    rng.range(1, self.state.map.width - 1),
+                    rng.range(1, self.state.map.height - 1),
+                )
+            })
+            .collect();
+
+        // which seed each tile is closest to
+        let membership: Vec<usize> = (0..self.state.map.tiles.len())
+            .map(|idx| {
+                let (x, y) = self.state.map.idx_xy(idx);
+                let here = Point::new(x, y);
+                seeds
+                    .iter()
+                    .enumerate()
+                    .map(|(i, seed)| (i, DistanceAlg::PythagorasSquared.distance2d(here, *seed)))
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(i, _)| i)
+                    .expect("There are no voronoi seeds")
+            })
+            .collect();
+
+        // tiles bordering more than one other cell become walls, everything else is floor
+        for y in 1..self.state.map.height - 1 {
+            for x in 1..self.state.map.width - 1 {
+                let idx = self.state.map.xy_idx(x, y);
+                let my_seed = membership[idx];
+                let neighbours = [
+                    self.state.map.xy_idx(x - 1, y),
+                    self.state.map.xy_idx(x + 1, y),
+                    self.state.map.xy_idx(x, y - 1),
+                    self.state.map.xy_idx(x, y + 1),
+                ]
+                .iter()
+                .filter(|n| membership[**n] != my_seed)
+                .count();
+
+                self.state.map.tiles[idx] = if neighbours < 2 {
+                    TileType::Floor
+                } else {
+                    TileType::Wall
+                };
+            }
+        }
+        wall_off_edges(&mut self.state.map);
+        self.take_snapshot();
+
+        let Some(start) = find_central_floor(&self.state.map) else {
+            return false;
+        };
+        self.state.starting_position = start;
+        let start_idx = self.state.map.xy_idx(
+            self.state.starting_position.x,
+            self.state.starting_position.y,
+        );
+        self.state.regions = cull_unreachable_regions(&mut self.state.map, start_idx);
+        // starting in a walled-in pocket culls away everything else
+        if !has_enough_floor(&self.state.map) {
+            return false;
+        }
+        let Some(exit_idx) = most_distant_tile(&mut self.state.map, start_idx) else {
+            return false;
+        };
+        self.state.map.tiles[exit_idx] = TileType::DownStairs;
+        self.take_snapshot();
+
+        self.spawn_regions = generate_voronoi_spawn_regions(&self.state.map, rng);
+        true
+    }
+}
+
+impl MapBuilder for VoronoiBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        // the cells can leave too little floor for a start and the stairs
+        while !self.build_cells(rng) {
+            self.state.restart();
+        }
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        spawn_regions_away_from_start(
+            ecs,
+            &self.state.map,
+            &self.spawn_regions,
+            &self.state.starting_position,
+        );
+    }
+
+    fn state(&self) -> &BuilderState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut BuilderState {
+        &mut self.state
+    }
+}
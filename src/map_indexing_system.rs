@@ -5,18 +5,26 @@ pub struct MapIndexingSystem {}
 
 impl<'a> System<'a> for MapIndexingSystem {
     type SystemData = (
+        Entities<'a>,
         WriteExpect<'a, Map>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, BlocksTiles>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut s_map, s_position, s_blocks_tiles) = data;
+        let (entities, mut s_map, s_position, s_blocks_tiles) = data;
 
         s_map.populate_blocked();
-        for (position, _blocks) in (&s_position, &s_blocks_tiles).join() {
+        s_map.clear_content_index();
+        for (entity, position) in (&entities, &s_position).join() {
             let idx = s_map.xy_idx(position.x, position.y);
-            s_map.blocked[idx] = true;
+
+            // blocking entities mark their tile as impassable
+            if s_blocks_tiles.get(entity).is_some() {
+                s_map.blocked[idx] = true;
+            }
+
+            s_map.tile_content[idx].push(entity);
         }
     }
 }
@@ -0,0 +1,88 @@
+use super::{
+    CombatStats, GameLog, Name, Noise, Player, Position, SufferDamage, WantsToMelee, COMBAT_VOLUME,
+};
+use rltk::RGB;
+use specs::prelude::*;
+
+pub struct MeleeCombatSystem {}
+
+impl<'a> System<'a> for MeleeCombatSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, WantsToMelee>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Player>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, Position>,
+        WriteExpect<'a, Noise>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            mut s_wants_melee,
+            s_name,
+            s_combat_stats,
+            mut s_inflict_damage,
+            s_player,
+            mut s_log,
+            s_pos,
+            mut s_noise,
+        ) = data;
+
+        for (entity, wants_melee, name, stats) in
+            (&entities, &s_wants_melee, &s_name, &s_combat_stats).join()
+        {
+            // dead entities don't get to swing
+            if stats.hp <= 0 {
+                continue;
+            }
+
+            let target_stats = s_combat_stats.get(wants_melee.target);
+            let target_name = s_name.get(wants_melee.target);
+            if let (Some(target_stats), Some(target_name)) = (target_stats, target_name) {
+                if target_stats.hp <= 0 {
+                    continue;
+                }
+
+                if let Some(pos) = s_pos.get(entity) {
+                    s_noise.make(pos.x, pos.y, COMBAT_VOLUME);
+                }
+
+                // blows landing on the player stand out from the player's own attacks
+                let color = if s_player.get(wants_melee.target).is_some() {
+                    RGB::named(rltk::RED)
+                } else {
+                    RGB::named(rltk::WHITE)
+                };
+
+                let damage = i32::max(0, stats.power - target_stats.defense);
+                if damage == 0 {
+                    s_log.log(
+                        format!("{} is unable to hurt {}", &name.name, &target_name.name),
+                        color,
+                    );
+                } else {
+                    s_log.log(
+                        format!(
+                            "{} hits {}, for {} hp.",
+                            &name.name, &target_name.name, damage
+                        ),
+                        color,
+                    );
+                    SufferDamage::new_damage(
+                        &mut s_inflict_damage,
+                        wants_melee.target,
+                        damage,
+                        &name.name,
+                    );
+                }
+            }
+        }
+
+        // every intent has been resolved for this turn
+        s_wants_melee.clear();
+    }
+}
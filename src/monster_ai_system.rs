@@ -1,50 +1,418 @@
-use super::{Map, Monster, Name, Position, Viewshed};
-use rltk::{console, Point, RandomNumberGenerator};
+use super::{
+    raws::{RawMaster, Reaction},
+    AiState, CombatStats, Confusion, Faction, GameLog, Home, Map, Monster, Name, Noise, Position,
+    Viewshed, WantsToMelee,
+};
+use rltk::{BaseMap, DijkstraMap, Point, RandomNumberGenerator, RGB};
 use specs::prelude::*;
 
+/// Monsters at or below this share of their max hp run from their enemies
+const FLEE_HP_PERCENT: i32 = 25;
+/// How far a wandering monster strays from home
+const WANDER_RADIUS: f32 = 6.0;
+/// How far ahead a fleeing monster plans its escape
+const FLEE_SEARCH_DEPTH: f32 = 20.0;
+
 pub struct MonsterAI {}
 
 impl<'a> System<'a> for MonsterAI {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
+        Entities<'a>,
         WriteStorage<'a, Viewshed>,
         WriteStorage<'a, Position>,
         ReadStorage<'a, Monster>,
         ReadExpect<'a, Point>,
+        ReadExpect<'a, Entity>,
         ReadStorage<'a, Name>,
         WriteExpect<'a, Map>,
+        WriteStorage<'a, WantsToMelee>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, Confusion>,
+        WriteStorage<'a, AiState>,
+        ReadStorage<'a, Home>,
+        ReadStorage<'a, CombatStats>,
+        WriteExpect<'a, Noise>,
+        ReadStorage<'a, Faction>,
+        ReadExpect<'a, RawMaster>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut s_viewshed, mut s_pos, s_monster, s_player_point, s_name, mut s_map) = data;
+        let (
+            entities,
+            mut s_viewshed,
+            mut s_pos,
+            s_monster,
+            s_player_point,
+            s_player_entity,
+            s_name,
+            mut s_map,
+            mut s_wants_to_melee,
+            mut s_rng,
+            mut s_log,
+            mut s_confused,
+            mut s_state,
+            s_home,
+            s_combat_stats,
+            mut s_noise,
+            s_faction,
+            s_raws,
+        ) = data;
         let idle_text: Vec<&str> = vec![
             "considers their own existence",
             "shouts at you",
             "scratches itself",
             "insults you",
         ];
-        let mut rng = RandomNumberGenerator::new();
+        // where everyone who belongs to a faction stood when the turn began
+        let sides: Vec<(Entity, Point, String)> = (&entities, &s_pos, &s_faction)
+            .join()
+            .map(|(other, pos, faction)| (other, Point::new(pos.x, pos.y), faction.name.clone()))
+            .collect();
 
-        let mut index = 0;
-        for (mut viewshed, mut pos, _monster, name) in
-            (&mut s_viewshed, &mut s_pos, &s_monster, &s_name).join()
+        for (entity, viewshed, pos, _monster, name, state) in (
+            &entities,
+            &mut s_viewshed,
+            &mut s_pos,
+            &s_monster,
+            &s_name,
+            &mut s_state,
+        )
+            .join()
         {
-            if viewshed.visible_tiles.contains(&s_player_point) {
-                if let Some(shout) = rng.random_slice_entry(&idle_text) {
-                    console::log(format!("({}) {} {}", index, name.name, *shout));
-                }
-                let path = rltk::a_star_search(
-                    s_map.xy_idx(pos.x, pos.y),
-                    s_map.xy_idx(s_player_point.x, s_player_point.y),
-                    &mut *s_map,
-                );
-                if path.success && path.steps.len() > 1 && !s_map.blocked[path.steps[1]] {
-                    let (x, y) = s_map.idx_xy(path.steps[1]);
-                    pos.x = x;
-                    pos.y = y;
-                    viewshed.dirty = true;
-                }
-            }
-            index = index + 1
+            // confused monsters lose their turn until the effect wears off
+            if let Some(confusion) = s_confused.get_mut(entity) {
+                confusion.turns -= 1;
+                if confusion.turns < 1 {
+                    s_confused.remove(entity);
+                    s_log.log(
+                        format!("{} is no longer confused", name.name),
+                        RGB::named(rltk::GREY),
+                    );
+                }
+                continue;
+            }
+
+            let home = s_home
+                .get(entity)
+                .map_or((pos.x, pos.y), |home| (home.x, home.y));
+            let me = Point::new(pos.x, pos.y);
+            let faction = s_faction.get(entity).map_or("", |f| f.name.as_str());
+            let hurt = s_combat_stats
+                .get(entity)
+                .is_some_and(|stats| stats.hp * 100 <= stats.max_hp * FLEE_HP_PERCENT);
+
+            // attack the closest visible enemy and keep away from anything frightening
+            let mut enemy: Option<(Entity, Point)> = None;
+            let mut threats: Vec<Point> = Vec::new();
+            for (other, at, their_faction) in sides.iter() {
+                if *other == entity || !viewshed.visible_tiles.contains(at) {
+                    continue;
+                }
+                match s_raws.reaction(faction, their_faction) {
+                    Reaction::Attack => {
+                        let closer = enemy.is_none_or(|(_, best)| {
+                            rltk::DistanceAlg::PythagorasSquared.distance2d(me, *at)
+                                < rltk::DistanceAlg::PythagorasSquared.distance2d(me, best)
+                        });
+                        if closer {
+                            enemy = Some((*other, *at));
+                        }
+                    }
+                    Reaction::Flee => threats.push(*at),
+                    Reaction::Ignore => {}
+                }
+            }
+            if hurt {
+                if let Some((_, at)) = enemy {
+                    threats.push(at);
+                }
+            }
+
+            let sees_player = viewshed.visible_tiles.contains(&s_player_point);
+            let senses = Senses {
+                position: (pos.x, pos.y),
+                home,
+                enemy: enemy.map(|(_, at)| (at.x, at.y)),
+                threatened: !threats.is_empty(),
+                heard: s_noise
+                    .heard_at(pos.x, pos.y, *state == AiState::Asleep)
+                    .map(|sound| (sound.x, sound.y)),
+                hurt,
+            };
+
+            let next = next_state(*state, &senses);
+            if sees_player && next != *state {
+                match next {
+                    AiState::Chasing { .. } if *state == AiState::Asleep => {
+                        s_log.log(format!("{} wakes up", name.name), RGB::named(rltk::GREY))
+                    }
+                    AiState::Chasing { .. }
+                        if !matches!(*state, AiState::Chasing { .. })
+                            && enemy.is_some_and(|(target, _)| target == *s_player_entity) =>
+                    {
+                        if let Some(shout) = s_rng.random_slice_entry(&idle_text) {
+                            s_log.log(format!("{} {}", name.name, *shout), RGB::named(rltk::GREY));
+                        }
+                    }
+                    AiState::Fleeing => s_log.log(
+                        format!("{} turns to flee", name.name),
+                        RGB::named(rltk::GREY),
+                    ),
+                    _ => {}
+                }
+            }
+            *state = next;
+
+            // adjacent monsters attack instead of trying to walk into their enemy,
+            // as long as they could have stepped there under the map's corner cutting rule
+            let in_reach = enemy.filter(|(_, at)| {
+                rltk::DistanceAlg::Pythagoras.distance2d(me, *at) < 1.5
+                    && s_map.diagonal_step_allowed(pos.x, pos.y, at.x - pos.x, at.y - pos.y)
+            });
+
+            let idx = s_map.xy_idx(pos.x, pos.y);
+            match *state {
+                AiState::Asleep => {}
+                AiState::Wandering => {
+                    if s_rng.roll_dice(1, 2) == 1 {
+                        let home = Point::new(home.0, home.1);
+                        let exits: Vec<usize> = s_map
+                            .get_available_exits(idx)
+                            .iter()
+                            .map(|(exit, _)| *exit)
+                            .filter(|exit| {
+                                let (x, y) = s_map.idx_xy(*exit);
+                                !s_map.blocked[*exit]
+                                    && rltk::DistanceAlg::Pythagoras
+                                        .distance2d(Point::new(x, y), home)
+                                        <= WANDER_RADIUS
+                            })
+                            .collect();
+                        if let Some(exit) = s_rng.random_slice_entry(&exits) {
+                            step_to(&mut s_map, pos, viewshed, *exit);
+                        }
+                    }
+                }
+                AiState::Chasing { x, y } => {
+                    if let Some((target, _)) = in_reach {
+                        s_wants_to_melee
+                            .insert(entity, WantsToMelee { target })
+                            .expect("Unable to insert attack");
+                        continue;
+                    }
+                    let target = s_map.xy_idx(x, y);
+                    if let Some(step) = first_step(&mut s_map, idx, target) {
+                        step_to(&mut s_map, pos, viewshed, step);
+                    }
+                }
+                AiState::Fleeing => {
+                    if threats.is_empty() {
+                        continue;
+                    }
+                    let map = &*s_map;
+                    let starts: Vec<usize> =
+                        threats.iter().map(|at| map.xy_idx(at.x, at.y)).collect();
+                    let flee_map =
+                        DijkstraMap::new(map.width, map.height, &starts, map, FLEE_SEARCH_DEPTH);
+                    let escape = DijkstraMap::find_highest_exit(&flee_map, idx, map)
+                        .filter(|exit| !s_map.blocked[*exit]);
+                    match (escape, in_reach) {
+                        (Some(exit), _) => step_to(&mut s_map, pos, viewshed, exit),
+                        // cornered
+                        (None, Some((target, _))) => {
+                            s_wants_to_melee
+                                .insert(entity, WantsToMelee { target })
+                                .expect("Unable to insert attack");
+                        }
+                        (None, None) => {}
+                    }
+                }
+                AiState::ReturningHome => {
+                    let target = s_map.xy_idx(home.0, home.1);
+                    if let Some(step) = first_step(&mut s_map, idx, target) {
+                        step_to(&mut s_map, pos, viewshed, step);
+                    }
+                }
+            }
         }
+
+        // every monster has had its chance to hear this turn's sounds
+        s_noise.sounds.clear();
+    }
+}
+
+/// What a monster knows when it decides what to do next
+struct Senses {
+    position: (i32, i32),
+    home: (i32, i32),
+    /// where the closest enemy in sight is
+    enemy: Option<(i32, i32)>,
+    /// something it fears is in sight, or an enemy is and it is badly hurt
+    threatened: bool,
+    /// the closest sound the monster heard this turn
+    heard: Option<(i32, i32)>,
+    hurt: bool,
+}
+
+fn next_state(state: AiState, senses: &Senses) -> AiState {
+    match state {
+        // sleepers only wake to noise; walking up to one quietly is fine
+        AiState::Asleep => match senses.heard {
+            Some(_) if senses.threatened => AiState::Fleeing,
+            Some((x, y)) => {
+                let (x, y) = senses.enemy.unwrap_or((x, y));
+                AiState::Chasing { x, y }
+            }
+            None => AiState::Asleep,
+        },
+        _ if senses.threatened => AiState::Fleeing,
+        // with no way to heal, a hurt monster that starts running keeps running
+        AiState::Fleeing if senses.hurt => AiState::Fleeing,
+        _ => {
+            if let Some((x, y)) = senses.enemy.or(senses.heard) {
+                return AiState::Chasing { x, y };
+            }
+            match state {
+                // reached the last known position without finding anyone
+                AiState::Chasing { x, y } if senses.position == (x, y) => AiState::ReturningHome,
+                // the danger is out of sight
+                AiState::Fleeing => AiState::ReturningHome,
+                // something else may be standing on the home tile itself
+                AiState::ReturningHome
+                    if rltk::DistanceAlg::Pythagoras.distance2d(
+                        Point::new(senses.position.0, senses.position.1),
+                        Point::new(senses.home.0, senses.home.1),
+                    ) < 1.5 =>
+                {
+                    AiState::Wandering
+                }
+                _ => state,
+            }
+        }
+    }
+}
+
+/// The first tile on the way from `start` to `end`. Blocked paths are waited out rather than given up on.
+fn first_step(map: &mut Map, start: usize, end: usize) -> Option<usize> {
+    // A* won't finish on a blocked tile, but the player or a monster may be standing on the goal
+    let end_blocked = map.blocked[end];
+    map.blocked[end] = false;
+    let path = rltk::a_star_search(start, end, &*map);
+    map.blocked[end] = end_blocked;
+    if path.success && path.steps.len() > 1 {
+        Some(path.steps[1])
+    } else {
+        None
+    }
+}
+
+/// Move the monster onto `idx` if nothing is standing there
+fn step_to(map: &mut Map, pos: &mut Position, viewshed: &mut Viewshed, idx: usize) {
+    if map.blocked[idx] {
+        return;
+    }
+    // keep the blocked index current so monsters don't stack on the same tile
+    let old_idx = map.xy_idx(pos.x, pos.y);
+    map.blocked[old_idx] = false;
+    let (x, y) = map.idx_xy(idx);
+    pos.x = x;
+    pos.y = y;
+    map.blocked[idx] = true;
+    viewshed.dirty = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn senses() -> Senses {
+        Senses {
+            position: (5, 5),
+            home: (1, 1),
+            enemy: None,
+            threatened: false,
+            heard: None,
+            hurt: false,
+        }
+    }
+
+    #[test]
+    fn sleepers_wake_to_noise_but_not_sight() {
+        let seen = Senses {
+            enemy: Some((6, 5)),
+            ..senses()
+        };
+        assert_eq!(next_state(AiState::Asleep, &seen), AiState::Asleep);
+
+        let heard = Senses {
+            heard: Some((8, 8)),
+            ..senses()
+        };
+        assert_eq!(
+            next_state(AiState::Asleep, &heard),
+            AiState::Chasing { x: 8, y: 8 }
+        );
+    }
+
+    #[test]
+    fn losing_sight_keeps_the_chase_going_to_the_last_known_position() {
+        let chasing = AiState::Chasing { x: 9, y: 9 };
+        assert_eq!(next_state(chasing, &senses()), chasing);
+
+        let arrived = Senses {
+            position: (9, 9),
+            ..senses()
+        };
+        assert_eq!(next_state(chasing, &arrived), AiState::ReturningHome);
+
+        let home = Senses {
+            position: (1, 1),
+            ..senses()
+        };
+        assert_eq!(
+            next_state(AiState::ReturningHome, &home),
+            AiState::Wandering
+        );
+    }
+
+    #[test]
+    fn badly_hurt_monsters_keep_fleeing_after_losing_sight() {
+        let hurt = Senses {
+            enemy: Some((6, 5)),
+            threatened: true,
+            hurt: true,
+            ..senses()
+        };
+        assert_eq!(
+            next_state(AiState::Chasing { x: 6, y: 5 }, &hurt),
+            AiState::Fleeing
+        );
+
+        let out_of_sight = Senses {
+            hurt: true,
+            ..senses()
+        };
+        assert_eq!(
+            next_state(AiState::Fleeing, &out_of_sight),
+            AiState::Fleeing
+        );
+    }
+
+    #[test]
+    fn frightened_monsters_go_home_once_the_danger_is_gone() {
+        let frightened = Senses {
+            threatened: true,
+            ..senses()
+        };
+        assert_eq!(
+            next_state(AiState::Wandering, &frightened),
+            AiState::Fleeing
+        );
+        assert_eq!(
+            next_state(AiState::Fleeing, &senses()),
+            AiState::ReturningHome
+        );
     }
 }
@@ -0,0 +1,44 @@
+use rltk::{DistanceAlg, Point};
+
+/// How far a player's footsteps carry
+pub const FOOTSTEP_VOLUME: i32 = 3;
+/// How far the sound of a melee attack carries
+pub const COMBAT_VOLUME: i32 = 10;
+
+pub struct Sound {
+    pub x: i32,
+    pub y: i32,
+    /// the distance in tiles an awake listener can hear it from
+    pub volume: i32,
+}
+
+/// Sounds made since the monsters last listened. `MonsterAI` clears it every turn.
+#[derive(Default)]
+pub struct Noise {
+    pub sounds: Vec<Sound>,
+}
+
+impl Noise {
+    pub fn make(&mut self, x: i32, y: i32, volume: i32) {
+        self.sounds.push(Sound { x, y, volume });
+    }
+
+    /// The closest sound a listener at (x, y) can hear. Sleepers only notice sounds at half the distance.
+    pub fn heard_at(&self, x: i32, y: i32, asleep: bool) -> Option<&Sound> {
+        let listener = Point::new(x, y);
+        let distance = |sound: &Sound| {
+            DistanceAlg::Pythagoras.distance2d(listener, Point::new(sound.x, sound.y))
+        };
+        self.sounds
+            .iter()
+            .filter(|sound| {
+                let range = if asleep {
+                    sound.volume / 2
+                } else {
+                    sound.volume
+                };
+                distance(sound) <= range as f32
+            })
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+    }
+}
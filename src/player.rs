@@ -1,37 +1,427 @@
-use super::{Map, Position, RunState, State, Viewshed};
-use rltk::{Point, Rltk, VirtualKeyCode};
+use super::{
+    camera, gui,
+    raws::{RawMaster, Reaction},
+    CombatStats, Confusion, Faction, GameLog, Item, Map, Monster, Noise, Position, RevealedMap,
+    RunState, State, TileType, Viewshed, WantsToMelee, WantsToPickupItem, FOOTSTEP_VOLUME,
+};
+use rltk::{Point, RandomNumberGenerator, Rltk, VirtualKeyCode, RGB};
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use specs_derive::Component;
-use std::cmp::{max, min};
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Player {}
 
+/// Something the player keeps doing turn after turn until it finishes or is interrupted
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub enum Activity {
+    /// wait in place, recovering 1 hp a turn, until fully healed
+    Resting,
+    /// walk toward the nearest tile that hasn't been seen yet
+    Exploring,
+}
+
+const STUMBLE_DIRECTIONS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
 pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
+    let entities = ecs.entities();
     let mut positions = ecs.write_storage::<Position>();
     let mut players = ecs.write_storage::<Player>();
     let mut viewshed = ecs.write_storage::<Viewshed>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
+    let mut confused = ecs.write_storage::<Confusion>();
+    let mut rng = ecs.write_resource::<RandomNumberGenerator>();
     let mut player_position = ecs.write_resource::<Point>();
+    let mut noise = ecs.write_resource::<Noise>();
     let map = ecs.fetch::<Map>();
 
-    for (_player, pos, vs) in (&mut players, &mut positions, &mut viewshed).join() {
+    for (entity, _player, pos, vs) in
+        (&entities, &mut players, &mut positions, &mut viewshed).join()
+    {
+        let (mut delta_x, mut delta_y) = (delta_x, delta_y);
+        // a confused player stumbles in any of the eight directions instead,
+        // bound by the same corner cutting rule as a deliberate step
+        if let Some(confusion) = confused.get_mut(entity) {
+            (delta_x, delta_y) = STUMBLE_DIRECTIONS[rng.range(0, STUMBLE_DIRECTIONS.len())];
+            confusion.turns -= 1;
+            if confusion.turns < 1 {
+                confused.remove(entity);
+            }
+        }
+
         let next_x = pos.x + delta_x;
         let next_y = pos.y + delta_y;
+        if next_x < 0 || next_x > map.width - 1 || next_y < 0 || next_y > map.height - 1 {
+            return;
+        }
+        if !map.diagonal_step_allowed(pos.x, pos.y, delta_x, delta_y) {
+            return;
+        }
+
+        // bumping into something with combat stats attacks it instead of moving
+        for potential_target in map.entities_at(next_x, next_y).iter() {
+            if combat_stats.get(*potential_target).is_some() {
+                wants_to_melee
+                    .insert(
+                        entity,
+                        WantsToMelee {
+                            target: *potential_target,
+                        },
+                    )
+                    .expect("Add target failed");
+                return;
+            }
+        }
+
         let next_idx = map.xy_idx(next_x, next_y);
         if !map.blocked[next_idx] {
             // only move the player if the next set of coords is not a wall tile
-            pos.x = min(79, max(0, next_x));
-            pos.y = min(49, max(0, next_y));
+            pos.x = next_x.clamp(0, map.width - 1);
+            pos.y = next_y.clamp(0, map.height - 1);
             // update global player position in the ecs
             player_position.x = pos.x;
             player_position.y = pos.y;
+            noise.make(pos.x, pos.y, FOOTSTEP_VOLUME);
             // now that the player has moved, set their viewshed to dirty to recaculate FoV
             vs.dirty = true;
         }
     }
 }
 
+/// Queue a pickup of the first item on the player's tile. Returns false when there is nothing to take.
+fn get_item(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let player_entity = ecs.fetch::<Entity>();
+    let items = ecs.read_storage::<Item>();
+    let map = ecs.fetch::<Map>();
+    let mut gamelog = ecs.write_resource::<GameLog>();
+
+    let target_item = map
+        .entities_at(player_pos.x, player_pos.y)
+        .iter()
+        .find(|entity| items.get(**entity).is_some());
+
+    match target_item {
+        None => {
+            gamelog.log("There is nothing here to pick up.", RGB::named(rltk::GREY));
+            false
+        }
+        Some(item) => {
+            let mut pickup = ecs.write_storage::<WantsToPickupItem>();
+            pickup
+                .insert(
+                    *player_entity,
+                    WantsToPickupItem {
+                        collected_by: *player_entity,
+                        item: *item,
+                    },
+                )
+                .expect("Unable to insert want to pickup");
+            true
+        }
+    }
+}
+
+/// Start a level change if the player is standing on up stairs
+fn try_previous_level(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+    if map.tiles[player_idx] == TileType::UpStairs {
+        true
+    } else {
+        ecs.write_resource::<GameLog>()
+            .log("There is no way up from here.", RGB::named(rltk::GREY));
+        false
+    }
+}
+
+/// Start a level change if the player is standing on down stairs
+fn try_next_level(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+    if map.tiles[player_idx] == TileType::DownStairs {
+        true
+    } else {
+        ecs.write_resource::<GameLog>()
+            .log("There is no way down from here.", RGB::named(rltk::GREY));
+        false
+    }
+}
+
+/// The rest of a multi-turn walk the player asked for with the mouse, as map indices, next step first
+#[derive(Component, Debug, Clone)]
+pub struct QueuedMovement {
+    pub path: Vec<usize>,
+}
+
+/// True if any monster that would attack the player stands on a tile the player can currently see
+pub fn hostile_in_view(ecs: &World) -> bool {
+    let player_entity = ecs.fetch::<Entity>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let monsters = ecs.read_storage::<Monster>();
+    let positions = ecs.read_storage::<Position>();
+    let factions = ecs.read_storage::<Faction>();
+    let raws = ecs.fetch::<RawMaster>();
+
+    let Some(viewshed) = viewsheds.get(*player_entity) else {
+        return false;
+    };
+    let player_faction = factions.get(*player_entity).map_or("", |f| f.name.as_str());
+    (&monsters, &positions, &factions)
+        .join()
+        .any(|(_monster, pos, faction)| {
+            viewshed.visible_tiles.contains(&Point::new(pos.x, pos.y))
+                && raws.reaction(&faction.name, player_faction) == Reaction::Attack
+        })
+}
+
+/// Begin a multi-turn activity, unless a monster is already in sight
+fn start_activity(ecs: &mut World, activity: Activity) -> RunState {
+    if hostile_in_view(ecs) {
+        ecs.write_resource::<GameLog>().log(
+            "You can't do that with enemies in sight!",
+            RGB::named(rltk::GREY),
+        );
+        return RunState::Paused;
+    }
+
+    let player_entity = *ecs.fetch::<Entity>();
+    ecs.write_storage::<Activity>()
+        .insert(player_entity, activity)
+        .expect("Unable to insert activity");
+    RunState::Paused
+}
+
+fn stop_activity(ecs: &mut World, message: &str) {
+    let player_entity = *ecs.fetch::<Entity>();
+    ecs.write_storage::<Activity>().remove(player_entity);
+    ecs.write_resource::<GameLog>()
+        .log(message, RGB::named(rltk::GREY));
+}
+
+/// Where exploring goes next
+enum ExploreStep {
+    /// the direction of the first step toward the nearest edge of what has been seen
+    Toward(i32, i32),
+    /// there is more to see, but something is standing in every way to it
+    Blocked,
+    /// no seen floor borders anything unseen
+    Finished,
+}
+
+fn explore_step(ecs: &World) -> ExploreStep {
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+
+    // only what the player has seen counts, so exploring doesn't know where the hidden floor is
+    let borders_unseen = |idx: usize| {
+        let (x, y) = map.idx_xy(idx);
+        (-1..=1).any(|dy| {
+            (-1..=1).any(|dx| {
+                let (nx, ny) = (x + dx, y + dy);
+                nx >= 0
+                    && nx < map.width
+                    && ny >= 0
+                    && ny < map.height
+                    && !map.revealed_tiles[map.xy_idx(nx, ny)]
+            })
+        })
+    };
+    let frontier: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| {
+            *idx != player_idx
+                && map.revealed_tiles[*idx]
+                && map.tiles[*idx] != TileType::Wall
+                && borders_unseen(*idx)
+        })
+        .collect();
+    if frontier.is_empty() {
+        return ExploreStep::Finished;
+    }
+
+    let known = RevealedMap(&map);
+    let dijkstra_map = rltk::DijkstraMap::new(
+        map.width as usize,
+        map.height as usize,
+        &frontier,
+        &known,
+        (map.width * map.height) as f32,
+    );
+    let Some(next_idx) = rltk::DijkstraMap::find_lowest_exit(&dijkstra_map, player_idx, &known)
+    else {
+        return ExploreStep::Blocked;
+    };
+    if dijkstra_map.map[next_idx] == f32::MAX {
+        return ExploreStep::Blocked;
+    }
+
+    let (next_x, next_y) = map.idx_xy(next_idx);
+    ExploreStep::Toward(next_x - player_pos.x, next_y - player_pos.y)
+}
+
+/// Take the next step of the player's current activity, if they have one.
+/// Any key press, or a monster coming into view, interrupts it.
+pub fn continue_activity(ecs: &mut World, ctx: &Rltk) -> Option<RunState> {
+    let player_entity = *ecs.fetch::<Entity>();
+    let activity = *ecs.read_storage::<Activity>().get(player_entity)?;
+
+    if ctx.key.is_some() {
+        stop_activity(ecs, "You stop what you were doing.");
+        return Some(RunState::Paused);
+    }
+    if hostile_in_view(ecs) {
+        stop_activity(ecs, "You spot an enemy and stop.");
+        return Some(RunState::Paused);
+    }
+
+    match activity {
+        Activity::Resting => {
+            let mut combat_stats = ecs.write_storage::<CombatStats>();
+            let stats = combat_stats
+                .get_mut(player_entity)
+                .expect("The player has no combat stats");
+            if stats.hp >= stats.max_hp {
+                drop(combat_stats);
+                stop_activity(ecs, "You feel rested.");
+                return Some(RunState::Paused);
+            }
+            stats.hp += 1;
+        }
+        Activity::Exploring => match explore_step(ecs) {
+            ExploreStep::Toward(delta_x, delta_y) => {
+                let before = *ecs.fetch::<Point>();
+                try_move_player(delta_x, delta_y, ecs);
+                // a step that went nowhere would only be tried again next turn
+                if *ecs.fetch::<Point>() == before {
+                    stop_activity(ecs, "Something is in the way.");
+                    return Some(RunState::Paused);
+                }
+            }
+            ExploreStep::Blocked => {
+                stop_activity(ecs, "Something is blocking the way.");
+                return Some(RunState::Paused);
+            }
+            ExploreStep::Finished => {
+                stop_activity(ecs, "There is nowhere left to explore.");
+                return Some(RunState::Paused);
+            }
+        },
+    }
+
+    Some(RunState::Running)
+}
+
+/// Plan a walk to the clicked tile if it has been seen and can be reached
+fn travel_to_click(ecs: &mut World, ctx: &Rltk) -> RunState {
+    let target = camera::get_viewport(ecs).screen_to_world(Point::from_tuple(ctx.mouse_pos()));
+    let path = {
+        let map = ecs.fetch::<Map>();
+        if target.x < 0 || target.x >= map.width || target.y < 0 || target.y >= map.height {
+            return RunState::Paused;
+        }
+        let target_idx = map.xy_idx(target.x, target.y);
+        if !map.revealed_tiles[target_idx] {
+            return RunState::Paused;
+        }
+        let player_pos = ecs.fetch::<Point>();
+        rltk::a_star_search(
+            map.xy_idx(player_pos.x, player_pos.y),
+            target_idx,
+            &RevealedMap(&map),
+        )
+    };
+
+    if !path.success || path.steps.len() < 2 {
+        ecs.write_resource::<GameLog>()
+            .log("You can't find a way there.", RGB::named(rltk::GREY));
+        return RunState::Paused;
+    }
+    if hostile_in_view(ecs) {
+        ecs.write_resource::<GameLog>().log(
+            "You can't do that with enemies in sight!",
+            RGB::named(rltk::GREY),
+        );
+        return RunState::Paused;
+    }
+
+    let player_entity = *ecs.fetch::<Entity>();
+    ecs.write_storage::<QueuedMovement>()
+        .insert(
+            player_entity,
+            QueuedMovement {
+                // the first step is where the player already stands
+                path: path.steps[1..].to_vec(),
+            },
+        )
+        .expect("Unable to queue movement");
+    RunState::Paused
+}
+
+/// Take the next step of a walk queued with the mouse, if there is one.
+/// Any key press, or a monster coming into view, cancels the rest of the walk.
+pub fn continue_queued_movement(ecs: &mut World, ctx: &Rltk) -> Option<RunState> {
+    let player_entity = *ecs.fetch::<Entity>();
+    let next_idx = *ecs
+        .read_storage::<QueuedMovement>()
+        .get(player_entity)?
+        .path
+        .first()?;
+
+    let interrupted = if ctx.key.is_some() {
+        Some("You stop walking.")
+    } else if hostile_in_view(ecs) {
+        Some("You spot an enemy and stop.")
+    } else {
+        None
+    };
+    if let Some(message) = interrupted {
+        ecs.write_storage::<QueuedMovement>().remove(player_entity);
+        ecs.write_resource::<GameLog>()
+            .log(message, RGB::named(rltk::GREY));
+        return Some(RunState::Paused);
+    }
+
+    let (delta_x, delta_y) = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Point>();
+        let (next_x, next_y) = map.idx_xy(next_idx);
+        (next_x - player_pos.x, next_y - player_pos.y)
+    };
+    let before = *ecs.fetch::<Point>();
+    try_move_player(delta_x, delta_y, ecs);
+
+    // stop once the walk is over, or as soon as something got in the way
+    let mut queued = ecs.write_storage::<QueuedMovement>();
+    let moved = *ecs.fetch::<Point>() != before;
+    match queued.get_mut(player_entity) {
+        Some(queue) if moved && queue.path.len() > 1 => {
+            queue.path.remove(0);
+        }
+        _ => {
+            queued.remove(player_entity);
+        }
+    }
+    Some(RunState::Running)
+}
+
 pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
+    if ctx.left_click {
+        return travel_to_click(&mut gs.ecs, ctx);
+    }
+
     match ctx.key {
         None => return RunState::Paused,
         Some(key) => match key {
@@ -51,6 +441,50 @@ pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
                 try_move_player(0, 1, &mut gs.ecs)
             }
 
+            // diagonals
+            VirtualKeyCode::Y | VirtualKeyCode::Numpad7 => try_move_player(-1, -1, &mut gs.ecs),
+
+            VirtualKeyCode::U | VirtualKeyCode::Numpad9 => try_move_player(1, -1, &mut gs.ecs),
+
+            VirtualKeyCode::B | VirtualKeyCode::Numpad1 => try_move_player(-1, 1, &mut gs.ecs),
+
+            VirtualKeyCode::N | VirtualKeyCode::Numpad3 => try_move_player(1, 1, &mut gs.ecs),
+
+            // let a turn pass without doing anything
+            VirtualKeyCode::Space | VirtualKeyCode::Numpad5 => {}
+
+            VirtualKeyCode::R => return start_activity(&mut gs.ecs, Activity::Resting),
+            VirtualKeyCode::O => return start_activity(&mut gs.ecs, Activity::Exploring),
+
+            VirtualKeyCode::G => {
+                if !get_item(&mut gs.ecs) {
+                    return RunState::Paused;
+                }
+            }
+            VirtualKeyCode::Period => {
+                if try_next_level(&mut gs.ecs) {
+                    return RunState::NextLevel;
+                }
+                return RunState::Paused;
+            }
+            VirtualKeyCode::Comma => {
+                if try_previous_level(&mut gs.ecs) {
+                    return RunState::PreviousLevel;
+                }
+                return RunState::Paused;
+            }
+            VirtualKeyCode::I => return RunState::ShowInventory,
+            VirtualKeyCode::D => return RunState::ShowDropItem,
+
+            VirtualKeyCode::Escape => {
+                return RunState::PauseMenu {
+                    selection: gui::PauseMenuSelection::Resume,
+                }
+            }
+
+            // open the full message history
+            VirtualKeyCode::M => return RunState::ShowLog { offset: 0 },
+
             _ => return RunState::Paused,
         },
     }
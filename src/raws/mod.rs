@@ -0,0 +1,520 @@
+use super::Renderable;
+use rltk::RGB;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+mod rawmaster;
+pub use rawmaster::*;
+
+/// Where designers edit entity definitions. Read at startup so changes don't need a rebuild.
+pub const RAW_FILE: &str = "raws/spawns.json";
+/// The same file as it was at build time, used when `RAW_FILE` isn't next to the game
+const EMBEDDED_RAWS: &str = include_str!("../../raws/spawns.json");
+
+/// Everything that can be spawned from data, exactly as written in the raws file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Raws {
+    pub player: PlayerRaw,
+    pub factions: Vec<FactionRaw>,
+    pub monsters: Vec<MonsterRaw>,
+    pub items: Vec<ItemRaw>,
+    pub props: Vec<PropRaw>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RenderableRaw {
+    /// a single character from code page 437
+    pub glyph: String,
+    /// "#rrggbb"
+    pub fg: String,
+    pub bg: String,
+    /// lower orders are drawn on top
+    pub order: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StatsRaw {
+    pub max_hp: i32,
+    pub defense: i32,
+    pub power: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerRaw {
+    pub name: String,
+    pub renderable: RenderableRaw,
+    pub vision_range: i32,
+    pub stats: StatsRaw,
+    pub faction: String,
+}
+
+/// The key in `FactionRaw::reactions` used for any faction without its own entry
+pub const DEFAULT_REACTION_KEY: &str = "Default";
+
+/// What members of one faction do on seeing a member of another
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Reaction {
+    Attack,
+    Flee,
+    Ignore,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FactionRaw {
+    pub name: String,
+    /// keyed by the other faction's name or `DEFAULT_REACTION_KEY`
+    pub reactions: HashMap<String, Reaction>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MonsterRaw {
+    pub name: String,
+    pub renderable: RenderableRaw,
+    pub vision_range: i32,
+    pub stats: StatsRaw,
+    pub faction: String,
+    pub spawn_weight: i32,
+    pub min_depth: i32,
+    pub max_depth: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ItemRaw {
+    pub name: String,
+    pub renderable: RenderableRaw,
+    #[serde(default)]
+    pub consumable: bool,
+    pub effects: ItemEffectsRaw,
+    pub spawn_weight: i32,
+    pub min_depth: i32,
+    pub max_depth: i32,
+}
+
+/// Each effect maps onto the component of the same name
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ItemEffectsRaw {
+    pub provides_healing: Option<i32>,
+    pub inflicts_damage: Option<i32>,
+    pub ranged: Option<i32>,
+    pub area_of_effect: Option<i32>,
+    pub confusion: Option<i32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PropRaw {
+    pub name: String,
+    pub renderable: RenderableRaw,
+    #[serde(default)]
+    pub blocks_tile: bool,
+    pub spawn_weight: i32,
+    pub min_depth: i32,
+    pub max_depth: i32,
+}
+
+impl RenderableRaw {
+    pub fn to_renderable(&self) -> Result<Renderable, String> {
+        let mut chars = self.glyph.chars();
+        let glyph = match (chars.next(), chars.next()) {
+            (Some(c), None) if c == ' ' || rltk::to_cp437(c) != 0 => rltk::to_cp437(c),
+            _ => {
+                return Err(format!(
+                    "glyph '{}' must be a single code page 437 character",
+                    self.glyph
+                ))
+            }
+        };
+        let colour = |field: &str, hex: &str| {
+            RGB::from_hex(hex)
+                .map_err(|_| format!("{} colour '{}' is not a \"#rrggbb\" value", field, hex))
+        };
+        Ok(Renderable {
+            glyph,
+            fg: colour("fg", &self.fg)?,
+            bg: colour("bg", &self.bg)?,
+            render_order: self.order,
+        })
+    }
+}
+
+/// Parse and validate raws, naming `source` in any error
+pub fn parse_raws(text: &str, source: &str) -> Result<Raws, String> {
+    let raws: Raws =
+        serde_json::from_str(text).map_err(|e| format!("Unable to read {}: {}", source, e))?;
+
+    let problems = validate(&raws);
+    if !problems.is_empty() {
+        return Err(format!(
+            "{} has {} problem(s):\n  {}",
+            source,
+            problems.len(),
+            problems.join("\n  ")
+        ));
+    }
+    Ok(raws)
+}
+
+/// Every problem found in the raws, each prefixed with the entry it belongs to
+fn validate(raws: &Raws) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut names: HashSet<String> = HashSet::new();
+
+    let mut check_name = |entry: &str, name: &str, problems: &mut Vec<String>| {
+        if name.trim().is_empty() {
+            problems.push(format!("{}: name must not be empty", entry));
+        } else if !names.insert(name.to_string()) {
+            problems.push(format!(
+                "{}: name '{}' is already used by another entry",
+                entry, name
+            ));
+        }
+    };
+
+    let mut factions: HashSet<&str> = HashSet::new();
+    for (i, faction) in raws.factions.iter().enumerate() {
+        if faction.name.trim().is_empty() || faction.name == DEFAULT_REACTION_KEY {
+            problems.push(format!(
+                "factions[{}]: '{}' can't be used as a faction name",
+                i, faction.name
+            ));
+        } else if !factions.insert(&faction.name) {
+            problems.push(format!(
+                "factions[{}]: faction '{}' is defined twice",
+                i, faction.name
+            ));
+        }
+    }
+    for (i, faction) in raws.factions.iter().enumerate() {
+        // sorted so the report doesn't depend on hash order
+        let mut others: Vec<&String> = faction.reactions.keys().collect();
+        others.sort();
+        for other in others {
+            if other != DEFAULT_REACTION_KEY && !factions.contains(other.as_str()) {
+                problems.push(format!(
+                    "factions[{}] '{}': has a reaction to unknown faction '{}'",
+                    i, faction.name, other
+                ));
+            }
+        }
+    }
+    let check_faction = |entry: &str, faction: &str, problems: &mut Vec<String>| {
+        if !factions.contains(faction) {
+            problems.push(format!("{}: faction '{}' is not defined", entry, faction));
+        }
+    };
+
+    let player = &raws.player;
+    check_name("player", &player.name, &mut problems);
+    check_faction("player", &player.faction, &mut problems);
+    check_renderable("player", &player.renderable, &mut problems);
+    check_vision("player", player.vision_range, &mut problems);
+    check_stats("player", &player.stats, &mut problems);
+
+    for (i, monster) in raws.monsters.iter().enumerate() {
+        let entry = format!("monsters[{}] '{}'", i, monster.name);
+        check_name(&entry, &monster.name, &mut problems);
+        check_renderable(&entry, &monster.renderable, &mut problems);
+        check_vision(&entry, monster.vision_range, &mut problems);
+        check_stats(&entry, &monster.stats, &mut problems);
+        check_faction(&entry, &monster.faction, &mut problems);
+        check_spawning(
+            &entry,
+            monster.spawn_weight,
+            monster.min_depth,
+            monster.max_depth,
+            &mut problems,
+        );
+    }
+
+    for (i, item) in raws.items.iter().enumerate() {
+        let entry = format!("items[{}] '{}'", i, item.name);
+        check_name(&entry, &item.name, &mut problems);
+        check_renderable(&entry, &item.renderable, &mut problems);
+        check_effects(&entry, &item.effects, &mut problems);
+        check_spawning(
+            &entry,
+            item.spawn_weight,
+            item.min_depth,
+            item.max_depth,
+            &mut problems,
+        );
+    }
+
+    for (i, prop) in raws.props.iter().enumerate() {
+        let entry = format!("props[{}] '{}'", i, prop.name);
+        check_name(&entry, &prop.name, &mut problems);
+        check_renderable(&entry, &prop.renderable, &mut problems);
+        check_spawning(
+            &entry,
+            prop.spawn_weight,
+            prop.min_depth,
+            prop.max_depth,
+            &mut problems,
+        );
+    }
+
+    problems
+}
+
+fn check_renderable(entry: &str, renderable: &RenderableRaw, problems: &mut Vec<String>) {
+    if let Err(e) = renderable.to_renderable() {
+        problems.push(format!("{}: {}", entry, e));
+    }
+}
+
+fn check_vision(entry: &str, vision_range: i32, problems: &mut Vec<String>) {
+    if vision_range < 1 {
+        problems.push(format!(
+            "{}: vision_range must be at least 1, got {}",
+            entry, vision_range
+        ));
+    }
+}
+
+fn check_stats(entry: &str, stats: &StatsRaw, problems: &mut Vec<String>) {
+    if stats.max_hp < 1 {
+        problems.push(format!(
+            "{}: max_hp must be at least 1, got {}",
+            entry, stats.max_hp
+        ));
+    }
+    if stats.defense < 0 || stats.power < 0 {
+        problems.push(format!("{}: defense and power must not be negative", entry));
+    }
+}
+
+fn check_spawning(
+    entry: &str,
+    spawn_weight: i32,
+    min_depth: i32,
+    max_depth: i32,
+    problems: &mut Vec<String>,
+) {
+    // an entry that can never be rolled is almost certainly a mistake, so say so
+    if spawn_weight < 1 {
+        problems.push(format!(
+            "{}: spawn_weight must be at least 1, got {}",
+            entry, spawn_weight
+        ));
+    }
+    if min_depth < 1 {
+        problems.push(format!(
+            "{}: min_depth must be at least 1, got {}",
+            entry, min_depth
+        ));
+    }
+    if max_depth < 1 {
+        problems.push(format!(
+            "{}: max_depth must be at least 1, got {}",
+            entry, max_depth
+        ));
+    } else if min_depth > max_depth {
+        problems.push(format!(
+            "{}: min_depth {} is deeper than max_depth {}",
+            entry, min_depth, max_depth
+        ));
+    }
+}
+
+fn check_effects(entry: &str, effects: &ItemEffectsRaw, problems: &mut Vec<String>) {
+    let amounts = [
+        ("provides_healing", effects.provides_healing),
+        ("inflicts_damage", effects.inflicts_damage),
+        ("ranged", effects.ranged),
+        ("area_of_effect", effects.area_of_effect),
+        ("confusion", effects.confusion),
+    ];
+    for (effect, amount) in amounts.iter() {
+        if let Some(amount) = amount {
+            if *amount < 1 {
+                problems.push(format!(
+                    "{}: {} must be at least 1, got {}",
+                    entry, effect, amount
+                ));
+            }
+        }
+    }
+
+    if amounts.iter().all(|(_, amount)| amount.is_none()) {
+        problems.push(format!("{}: has no effects", entry));
+    }
+    if effects.area_of_effect.is_some() && effects.ranged.is_none() {
+        problems.push(format!("{}: area_of_effect needs a ranged target", entry));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Just enough of every section to be valid, so each test can break exactly one thing
+    const FIXTURE: &str = r##"{
+      "player": {
+        "name": "Player",
+        "renderable": { "glyph": "@", "fg": "#ffff00", "bg": "#000000", "order": 0 },
+        "vision_range": 8,
+        "stats": { "max_hp": 30, "defense": 2, "power": 5 },
+        "faction": "Player"
+      },
+      "factions": [
+        { "name": "Player", "reactions": { "Default": "attack" } },
+        { "name": "Goblins", "reactions": { "Default": "ignore", "Player": "attack" } },
+        { "name": "Orcs", "reactions": { "Player": "attack", "Goblins": "flee" } }
+      ],
+      "monsters": [
+        {
+          "name": "Goblin",
+          "renderable": { "glyph": "g", "fg": "#ff0000", "bg": "#000000", "order": 1 },
+          "vision_range": 8,
+          "stats": { "max_hp": 10, "defense": 1, "power": 3 },
+          "faction": "Goblins",
+          "spawn_weight": 10,
+          "min_depth": 1,
+          "max_depth": 4
+        },
+        {
+          "name": "Orc",
+          "renderable": { "glyph": "o", "fg": "#ff0000", "bg": "#000000", "order": 1 },
+          "vision_range": 8,
+          "stats": { "max_hp": 16, "defense": 1, "power": 4 },
+          "faction": "Orcs",
+          "spawn_weight": 5,
+          "min_depth": 2,
+          "max_depth": 8
+        }
+      ],
+      "items": [
+        {
+          "name": "Potion",
+          "renderable": { "glyph": "!", "fg": "#ff00ff", "bg": "#000000", "order": 2 },
+          "consumable": true,
+          "effects": { "provides_healing": 8 },
+          "spawn_weight": 7,
+          "min_depth": 1,
+          "max_depth": 100
+        }
+      ],
+      "props": [
+        {
+          "name": "Barrel",
+          "renderable": { "glyph": "0", "fg": "#8b4513", "bg": "#000000", "order": 3 },
+          "blocks_tile": true,
+          "spawn_weight": 2,
+          "min_depth": 1,
+          "max_depth": 100
+        }
+      ]
+    }"##;
+
+    fn parse_error(text: &str) -> String {
+        parse_raws(text, "test raws").expect_err("Broken raws were accepted")
+    }
+
+    #[test]
+    fn built_in_raws_are_valid() {
+        if let Err(e) = parse_raws(EMBEDDED_RAWS, "built-in raws") {
+            panic!("{}", e);
+        }
+    }
+
+    #[test]
+    fn the_fixture_is_valid() {
+        if let Err(e) = parse_raws(FIXTURE, "test raws") {
+            panic!("{}", e);
+        }
+    }
+
+    #[test]
+    fn problems_name_the_entry_and_the_field() {
+        let text = FIXTURE
+            .replace("\"max_depth\": 8", "\"max_depth\": 1")
+            .replace("\"glyph\": \"g\"", "\"glyph\": \"gg\"");
+        let error = parse_error(&text);
+
+        assert!(error.starts_with("test raws has 2 problem(s)"), "{}", error);
+        assert!(
+            error.contains("monsters[1] 'Orc': min_depth 2 is deeper than max_depth 1"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains(
+                "monsters[0] 'Goblin': glyph 'gg' must be a single code page 437 character"
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn entries_that_can_never_spawn_are_rejected() {
+        let text = FIXTURE.replace("\"spawn_weight\": 7", "\"spawn_weight\": 0");
+        let error = parse_error(&text);
+        assert!(
+            error.contains("items[0] 'Potion': spawn_weight must be at least 1, got 0"),
+            "{}",
+            error
+        );
+
+        let text = FIXTURE.replace(
+            "\"min_depth\": 1,\n          \"max_depth\": 4",
+            "\"min_depth\": 1,\n          \"max_depth\": 0",
+        );
+        let error = parse_error(&text);
+        assert!(error.starts_with("test raws has 1 problem(s)"), "{}", error);
+        assert!(
+            error.contains("monsters[0] 'Goblin': max_depth must be at least 1, got 0"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn factions_must_be_defined() {
+        let text = FIXTURE.replace("\"Goblins\": \"flee\"", "\"Gnomes\": \"flee\"");
+        let error = parse_error(&text);
+        assert!(
+            error.contains("factions[2] 'Orcs': has a reaction to unknown faction 'Gnomes'"),
+            "{}",
+            error
+        );
+
+        let text = FIXTURE.replace("\"faction\": \"Orcs\"", "\"faction\": \"Orks\"");
+        let error = parse_error(&text);
+        assert!(
+            error.contains("monsters[1] 'Orc': faction 'Orks' is not defined"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let text = FIXTURE.replacen("\"vision_range\": 8,", "\"vison_range\": 8,", 1);
+        let error = parse_error(&text);
+        assert!(error.contains("unknown field `vison_range`"), "{}", error);
+    }
+
+    #[test]
+    fn reactions_fall_back_to_the_default_then_to_ignoring() {
+        let raws = RawMaster::from_json(FIXTURE, "test raws").expect("Fixture should be valid");
+
+        // a reaction of its own wins over the default
+        assert_eq!(raws.reaction("Goblins", "Player"), Reaction::Attack);
+        assert_eq!(raws.reaction("Orcs", "Goblins"), Reaction::Flee);
+        // anyone else gets the default
+        assert_eq!(raws.reaction("Goblins", "Orcs"), Reaction::Ignore);
+        assert_eq!(raws.reaction("Player", "Goblins"), Reaction::Attack);
+        // no default and no entry, or an unknown faction, means ignore
+        assert_eq!(raws.reaction("Orcs", "Orcs"), Reaction::Ignore);
+        assert_eq!(raws.reaction("Gnomes", "Player"), Reaction::Ignore);
+    }
+}
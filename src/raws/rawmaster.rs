@@ -0,0 +1,254 @@
+use super::{
+    parse_raws, ItemRaw, MonsterRaw, PropRaw, Raws, Reaction, DEFAULT_REACTION_KEY, EMBEDDED_RAWS,
+    RAW_FILE,
+};
+use crate::spawn_table::SpawnTable;
+use crate::{
+    AiState, AreaOfEffect, BlocksTiles, CombatStats, Confusion, Consumable, Faction, Home,
+    InflictsDamage, Item, Monster, Name, Player, Position, ProvidesHealing, Ranged, SerializeMe,
+    Viewshed,
+};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use specs::saveload::{MarkedBuilder, SimpleMarker};
+use std::collections::HashMap;
+
+/// Validated raws plus a name index, stored as a resource so anything with the `World` can spawn
+#[derive(Clone)]
+pub struct RawMaster {
+    raws: Raws,
+    monster_index: HashMap<String, usize>,
+    item_index: HashMap<String, usize>,
+    prop_index: HashMap<String, usize>,
+    faction_index: HashMap<String, usize>,
+}
+
+impl RawMaster {
+    /// Read `RAW_FILE` from disk, or the copy built into the binary if there is no such file
+    pub fn load() -> Result<RawMaster, String> {
+        match std::fs::read_to_string(RAW_FILE) {
+            Ok(text) => RawMaster::from_json(&text, RAW_FILE),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RawMaster::embedded(),
+            Err(e) => Err(format!("Unable to read {}: {}", RAW_FILE, e)),
+        }
+    }
+
+    /// The raws as they were when the binary was built
+    pub fn embedded() -> Result<RawMaster, String> {
+        RawMaster::from_json(EMBEDDED_RAWS, "built-in raws")
+    }
+
+    pub fn from_json(text: &str, source: &str) -> Result<RawMaster, String> {
+        let raws = parse_raws(text, source)?;
+        let index = |names: Vec<&String>| -> HashMap<String, usize> {
+            names
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| (name.clone(), i))
+                .collect()
+        };
+        Ok(RawMaster {
+            monster_index: index(raws.monsters.iter().map(|m| &m.name).collect()),
+            item_index: index(raws.items.iter().map(|i| &i.name).collect()),
+            prop_index: index(raws.props.iter().map(|p| &p.name).collect()),
+            faction_index: index(raws.factions.iter().map(|f| &f.name).collect()),
+            raws,
+        })
+    }
+
+    /// Every monster, item and prop allowed at `depth`, weighted by its `spawn_weight`
+    pub fn spawn_table(&self, depth: i32) -> SpawnTable {
+        let mut table = SpawnTable::new();
+        let allowed = |min_depth: i32, max_depth: i32| depth >= min_depth && depth <= max_depth;
+        for monster in self.raws.monsters.iter() {
+            if allowed(monster.min_depth, monster.max_depth) {
+                table = table.add(&monster.name, monster.spawn_weight);
+            }
+        }
+        for item in self.raws.items.iter() {
+            if allowed(item.min_depth, item.max_depth) {
+                table = table.add(&item.name, item.spawn_weight);
+            }
+        }
+        for prop in self.raws.props.iter() {
+            if allowed(prop.min_depth, prop.max_depth) {
+                table = table.add(&prop.name, prop.spawn_weight);
+            }
+        }
+        table
+    }
+
+    /// How members of `faction` treat members of `other`. Unknown factions are ignored.
+    pub fn reaction(&self, faction: &str, other: &str) -> Reaction {
+        let Some(i) = self.faction_index.get(faction) else {
+            return Reaction::Ignore;
+        };
+        let reactions = &self.raws.factions[*i].reactions;
+        reactions
+            .get(other)
+            .or_else(|| reactions.get(DEFAULT_REACTION_KEY))
+            .copied()
+            .unwrap_or(Reaction::Ignore)
+    }
+
+    pub fn monster(&self, name: &str) -> Option<&MonsterRaw> {
+        self.monster_index
+            .get(name)
+            .map(|i| &self.raws.monsters[*i])
+    }
+
+    pub fn item(&self, name: &str) -> Option<&ItemRaw> {
+        self.item_index.get(name).map(|i| &self.raws.items[*i])
+    }
+
+    pub fn prop(&self, name: &str) -> Option<&PropRaw> {
+        self.prop_index.get(name).map(|i| &self.raws.props[*i])
+    }
+}
+
+/// Spawn the monster, item or prop called `name` at (x, y). Returns `None` if no template has that name.
+pub fn spawn_named_entity(ecs: &mut World, name: &str, x: i32, y: i32) -> Option<Entity> {
+    let raws = ecs.fetch::<RawMaster>();
+    if let Some(monster) = raws.monster(name).cloned() {
+        drop(raws);
+        return Some(spawn_monster(ecs, &monster, x, y));
+    }
+    if let Some(item) = raws.item(name).cloned() {
+        drop(raws);
+        return Some(spawn_item(ecs, &item, x, y));
+    }
+    if let Some(prop) = raws.prop(name).cloned() {
+        drop(raws);
+        return Some(spawn_prop(ecs, &prop, x, y));
+    }
+    None
+}
+
+/// Spawn the player from the raws' player template
+pub fn spawn_player(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let player = ecs.fetch::<RawMaster>().raws.player.clone();
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(
+            player
+                .renderable
+                .to_renderable()
+                .expect("Raws were validated on load"),
+        )
+        .with(Viewshed {
+            visible_tiles: Vec::new(),
+            range: player.vision_range,
+            dirty: true,
+        })
+        .with(Player {})
+        .with(Faction {
+            name: player.faction,
+        })
+        .with(Name { name: player.name })
+        .with(BlocksTiles {})
+        .with(CombatStats {
+            max_hp: player.stats.max_hp,
+            hp: player.stats.max_hp,
+            defense: player.stats.defense,
+            power: player.stats.power,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+fn spawn_monster(ecs: &mut World, monster: &MonsterRaw, x: i32, y: i32) -> Entity {
+    // some monsters are caught napping
+    let state = if ecs
+        .write_resource::<RandomNumberGenerator>()
+        .roll_dice(1, 2)
+        == 1
+    {
+        AiState::Asleep
+    } else {
+        AiState::Wandering
+    };
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(
+            monster
+                .renderable
+                .to_renderable()
+                .expect("Raws were validated on load"),
+        )
+        .with(Viewshed {
+            visible_tiles: Vec::new(),
+            range: monster.vision_range,
+            dirty: true,
+        })
+        .with(Monster {})
+        .with(state)
+        .with(Home { x, y })
+        .with(Faction {
+            name: monster.faction.clone(),
+        })
+        .with(Name {
+            name: monster.name.clone(),
+        })
+        .with(BlocksTiles {})
+        .with(CombatStats {
+            max_hp: monster.stats.max_hp,
+            hp: monster.stats.max_hp,
+            defense: monster.stats.defense,
+            power: monster.stats.power,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+fn spawn_item(ecs: &mut World, item: &ItemRaw, x: i32, y: i32) -> Entity {
+    let effects = &item.effects;
+    let mut builder = ecs
+        .create_entity()
+        .with(Position { x, y })
+        .with(
+            item.renderable
+                .to_renderable()
+                .expect("Raws were validated on load"),
+        )
+        .with(Name {
+            name: item.name.clone(),
+        })
+        .with(Item {});
+    if item.consumable {
+        builder = builder.with(Consumable {});
+    }
+    if let Some(heal_amount) = effects.provides_healing {
+        builder = builder.with(ProvidesHealing { heal_amount });
+    }
+    if let Some(damage) = effects.inflicts_damage {
+        builder = builder.with(InflictsDamage { damage });
+    }
+    if let Some(range) = effects.ranged {
+        builder = builder.with(Ranged { range });
+    }
+    if let Some(radius) = effects.area_of_effect {
+        builder = builder.with(AreaOfEffect { radius });
+    }
+    if let Some(turns) = effects.confusion {
+        builder = builder.with(Confusion { turns });
+    }
+    builder.marked::<SimpleMarker<SerializeMe>>().build()
+}
+
+fn spawn_prop(ecs: &mut World, prop: &PropRaw, x: i32, y: i32) -> Entity {
+    let mut builder = ecs
+        .create_entity()
+        .with(Position { x, y })
+        .with(
+            prop.renderable
+                .to_renderable()
+                .expect("Raws were validated on load"),
+        )
+        .with(Name {
+            name: prop.name.clone(),
+        });
+    if prop.blocks_tile {
+        builder = builder.with(BlocksTiles {});
+    }
+    builder.marked::<SimpleMarker<SerializeMe>>().build()
+}
@@ -0,0 +1,283 @@
+use super::components::*;
+use super::{
+    raws::RawMaster, register_components, CornerCutting, GameLog, Map, MasterDungeonMap, Noise,
+    Player, RunState,
+};
+use rltk::{Point, RandomNumberGenerator};
+use serde::{Deserialize, Serialize};
+use specs::error::NoError;
+use specs::prelude::*;
+use specs::saveload::{
+    DeserializeComponents, MarkedBuilder, SerializeComponents, SimpleMarker, SimpleMarkerAllocator,
+};
+use std::fs;
+use std::path::Path;
+
+pub const SAVE_FILE: &str = "./savegame.json";
+/// Bump whenever the saved components or their layout change
+pub const SAVE_VERSION: u32 = 10;
+
+/// The `RunState` a loaded game resumes in. Menus and the map generation playback
+/// aren't worth restoring, so they come back as the turn they interrupted.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum SavedRunState {
+    Paused,
+    Running,
+    NextLevel,
+    PreviousLevel,
+}
+
+impl SavedRunState {
+    fn from_runstate(runstate: RunState) -> SavedRunState {
+        match runstate {
+            RunState::Running | RunState::MapGeneration => SavedRunState::Running,
+            RunState::NextLevel => SavedRunState::NextLevel,
+            RunState::PreviousLevel => SavedRunState::PreviousLevel,
+            _ => SavedRunState::Paused,
+        }
+    }
+
+    fn to_runstate(self) -> RunState {
+        match self {
+            SavedRunState::Paused => RunState::Paused,
+            SavedRunState::Running => RunState::Running,
+            SavedRunState::NextLevel => RunState::NextLevel,
+            SavedRunState::PreviousLevel => RunState::PreviousLevel,
+        }
+    }
+}
+
+/// Written ahead of the component data so incompatible saves are rejected before touching the world
+#[derive(Serialize, Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+macro_rules! serialize_individually {
+    ($ecs:expr, $ser:expr, $data:expr, $( $type:ty),*) => {
+        $(
+        SerializeComponents::<NoError, SimpleMarker<SerializeMe>>::serialize(
+            &( $ecs.read_storage::<$type>(), ),
+            &$data.0,
+            &$data.1,
+            &mut $ser,
+        )
+        .map_err(|e| format!("Unable to write {}: {}", stringify!($type), e))?;
+        )*
+    };
+}
+
+macro_rules! deserialize_individually {
+    ($ecs:expr, $de:expr, $data:expr, $( $type:ty),*) => {
+        $(
+        DeserializeComponents::<NoError, _>::deserialize(
+            &mut ( &mut $ecs.write_storage::<$type>(), ),
+            &$data.0,
+            &mut $data.1,
+            &mut $data.2,
+            &mut $de,
+        )
+        .map_err(|e| format!("Unable to read {}: {}", stringify!($type), e))?;
+        )*
+    };
+}
+
+/// Write every marked entity plus the map, stored levels, log, rng and run state to the save file
+pub fn save_game(ecs: &mut World, runstate: RunState) -> Result<(), String> {
+    // stash the resources on a temporary entity so they travel with the components
+    let helper = SerializationHelper {
+        map: (*ecs.fetch::<Map>()).clone(),
+        dungeon_master: (*ecs.fetch::<MasterDungeonMap>()).clone(),
+        log: (*ecs.fetch::<GameLog>()).clone(),
+        rng: (*ecs.fetch::<RandomNumberGenerator>()).clone(),
+        runstate: SavedRunState::from_runstate(runstate),
+    };
+    let save_helper = ecs
+        .create_entity()
+        .with(helper)
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+
+    let result = write_save(ecs);
+
+    ecs.delete_entity(save_helper)
+        .expect("Unable to delete save helper");
+    result
+}
+
+fn write_save(ecs: &World) -> Result<(), String> {
+    let data = (
+        ecs.entities(),
+        ecs.read_storage::<SimpleMarker<SerializeMe>>(),
+    );
+
+    let writer = fs::File::create(SAVE_FILE)
+        .map_err(|e| format!("Unable to create {}: {}", SAVE_FILE, e))?;
+    let mut serializer = serde_json::Serializer::new(writer);
+    SaveHeader {
+        version: SAVE_VERSION,
+    }
+    .serialize(&mut serializer)
+    .map_err(|e| format!("Unable to write save header: {}", e))?;
+
+    serialize_individually!(
+        ecs,
+        serializer,
+        data,
+        Position,
+        OtherLevelPosition,
+        Renderable,
+        Player,
+        Viewshed,
+        Monster,
+        AiState,
+        Home,
+        Faction,
+        Name,
+        BlocksTiles,
+        CombatStats,
+        Item,
+        InBackpack,
+        Consumable,
+        ProvidesHealing,
+        InflictsDamage,
+        Ranged,
+        AreaOfEffect,
+        Confusion,
+        SerializationHelper
+    );
+
+    Ok(())
+}
+
+pub fn does_save_exist() -> bool {
+    Path::new(SAVE_FILE).exists()
+}
+
+/// Replace the whole world with the contents of the save file and return the state to resume in.
+/// The save is read into a fresh world first, so a broken file leaves the current one untouched.
+pub fn load_game(ecs: &mut World) -> Result<RunState, String> {
+    let data = fs::read_to_string(SAVE_FILE)
+        .map_err(|e| format!("Unable to read {}: {}", SAVE_FILE, e))?;
+    let mut loaded = World::new();
+    register_components(&mut loaded);
+    let runstate = read_save(&mut loaded, &data)?;
+
+    // settings chosen at startup aren't part of the save
+    loaded.insert(*ecs.fetch::<CornerCutting>());
+    loaded.insert((*ecs.fetch::<RawMaster>()).clone());
+    loaded.insert(Noise::default());
+    *ecs = loaded;
+    Ok(runstate)
+}
+
+fn read_save(ecs: &mut World, data: &str) -> Result<RunState, String> {
+    let mut de = serde_json::Deserializer::from_str(data);
+
+    let header = SaveHeader::deserialize(&mut de)
+        .map_err(|e| format!("Save file header is unreadable: {}", e))?;
+    if header.version != SAVE_VERSION {
+        return Err(format!(
+            "Save file version {} is not supported (expected {})",
+            header.version, SAVE_VERSION
+        ));
+    }
+
+    {
+        let mut d = (
+            &mut ecs.entities(),
+            &mut ecs.write_storage::<SimpleMarker<SerializeMe>>(),
+            &mut ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>(),
+        );
+
+        deserialize_individually!(
+            ecs,
+            de,
+            d,
+            Position,
+            OtherLevelPosition,
+            Renderable,
+            Player,
+            Viewshed,
+            Monster,
+            AiState,
+            Home,
+            Faction,
+            Name,
+            BlocksTiles,
+            CombatStats,
+            Item,
+            InBackpack,
+            Consumable,
+            ProvidesHealing,
+            InflictsDamage,
+            Ranged,
+            AreaOfEffect,
+            Confusion,
+            SerializationHelper
+        );
+    }
+
+    // move the stashed resources back into the world
+    let helper = {
+        let entities = ecs.entities();
+        let helpers = ecs.read_storage::<SerializationHelper>();
+        (&entities, &helpers)
+            .join()
+            .map(|(entity, helper)| (entity, helper.clone()))
+            .next()
+    };
+    let (helper_entity, helper) = helper.ok_or("Save file is missing the map")?;
+    let mut map = helper.map;
+    map.tile_content = vec![Vec::new(); (map.width * map.height) as usize];
+    ecs.insert(map);
+    ecs.insert(helper.dungeon_master);
+    ecs.insert(helper.log);
+    ecs.insert(helper.rng);
+    let runstate = helper.runstate.to_runstate();
+    ecs.delete_entity(helper_entity)
+        .expect("Unable to delete helper");
+
+    let player = {
+        let entities = ecs.entities();
+        let players = ecs.read_storage::<Player>();
+        let positions = ecs.read_storage::<Position>();
+        (&entities, &players, &positions)
+            .join()
+            .map(|(entity, _player, pos)| (entity, Point::new(pos.x, pos.y)))
+            .next()
+    };
+    let (player_entity, player_pos) = player.ok_or("Save file is missing the player")?;
+    ecs.insert(player_pos);
+    ecs.insert(player_entity);
+
+    Ok(runstate)
+}
+
+pub fn delete_save() -> Result<(), String> {
+    if does_save_exist() {
+        fs::remove_file(SAVE_FILE).map_err(|e| format!("Unable to delete {}: {}", SAVE_FILE, e))?;
+    }
+    Ok(())
+}
+
+/// Encodes colours as portable "#rrggbb" strings instead of raw floats
+pub mod rgb_hex {
+    use rltk::RGB;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &RGB, serializer: S) -> Result<S::Ok, S::Error> {
+        let to_byte = |channel: f32| (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+        serializer.serialize_str(&format!(
+            "#{:02x}{:02x}{:02x}",
+            to_byte(color.r),
+            to_byte(color.g),
+            to_byte(color.b)
+        ))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RGB, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        RGB::from_hex(&hex).map_err(|_| D::Error::custom(format!("invalid colour '{}'", hex)))
+    }
+}
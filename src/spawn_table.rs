@@ -0,0 +1,79 @@
+use rltk::RandomNumberGenerator;
+
+/// A name that can be rolled from a `SpawnTable`, and how often relative to the others
+pub struct SpawnEntry {
+    pub name: String,
+    pub weight: i32,
+}
+
+/// Weighted list of entity names to spawn. Depth limits are applied when the table is built,
+/// see `RawMaster::spawn_table`.
+#[derive(Default)]
+pub struct SpawnTable {
+    entries: Vec<SpawnEntry>,
+    total_weight: i32,
+}
+
+impl SpawnTable {
+    pub fn new() -> SpawnTable {
+        SpawnTable::default()
+    }
+
+    /// Add an entry. Zero weights are dropped so they can never be rolled.
+    pub fn add<S: ToString>(mut self, name: S, weight: i32) -> SpawnTable {
+        if weight > 0 {
+            self.total_weight += weight;
+            self.entries.push(SpawnEntry {
+                name: name.to_string(),
+                weight,
+            });
+        }
+        self
+    }
+
+    /// Pick a name with probability weight / total weight, or `None` if the table is empty
+    pub fn roll(&self, rng: &mut RandomNumberGenerator) -> Option<&str> {
+        if self.total_weight == 0 {
+            return None;
+        }
+        let mut roll = rng.roll_dice(1, self.total_weight) - 1;
+        for entry in self.entries.iter() {
+            if roll < entry.weight {
+                return Some(&entry.name);
+            }
+            roll -= entry.weight;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolls_follow_the_weights() {
+        let table = SpawnTable::new()
+            .add("common", 3)
+            .add("never", 0)
+            .add("rare", 1);
+        let mut rng = RandomNumberGenerator::seeded(7);
+        let mut common = 0;
+        for _ in 0..4000 {
+            match table.roll(&mut rng) {
+                Some("common") => common += 1,
+                Some("rare") => {}
+                other => panic!("rolled {:?}", other),
+            }
+        }
+        // expect 3000; allow plenty of slack for the RNG
+        assert!((2800..3200).contains(&common), "common rolled {}", common);
+    }
+
+    #[test]
+    fn empty_table_rolls_nothing() {
+        let mut rng = RandomNumberGenerator::seeded(7);
+        assert!(SpawnTable::new().roll(&mut rng).is_none());
+        assert!(SpawnTable::new().add("never", 0).roll(&mut rng).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+use super::{raws, BlocksTiles, Map, Position, Rect, TileType};
+use rltk::{Algorithm2D, Point, RandomNumberGenerator};
+use specs::prelude::*;
+use std::collections::HashSet;
+
+/// Most rolls on the spawn table a room gets at depth 1; deeper rooms get one more every other level
+const MAX_SPAWNS: i32 = 4;
+
+/// Spawn the player and return its entity
+pub fn player(ecs: &mut World, x: i32, y: i32) -> Entity {
+    raws::spawn_player(ecs, x, y)
+}
+
+/// Fill the floor of a room from the depth's spawn table
+pub fn spawn_room(ecs: &mut World, map: &Map, room: &Rect, depth: i32) {
+    let mut area = Vec::new();
+    for y in room.y1 + 1..=room.y2 {
+        for x in room.x1 + 1..=room.x2 {
+            area.push(map.xy_idx(x, y));
+        }
+    }
+    spawn_region(ecs, map, &area, depth);
+}
+
+/// Roll the depth's spawn table a few times and put each result on its own free floor tile of the area.
+/// Tiles that already hold an entity are skipped, so spawns never pile up on each other. The player
+/// isn't on the level yet when this runs, so keeping the start clear is up to the builder, which
+/// never spawns into the room or region the player starts in.
+pub fn spawn_region(ecs: &mut World, map: &Map, area: &[usize], depth: i32) {
+    let (mut occupied, mut blocked) = {
+        let positions = ecs.read_storage::<Position>();
+        let blockers = ecs.read_storage::<BlocksTiles>();
+        let mut occupied = HashSet::new();
+        let mut blocked = HashSet::new();
+        for (pos, blocks) in (&positions, blockers.maybe()).join() {
+            if map.in_bounds(Point::new(pos.x, pos.y)) {
+                let idx = map.xy_idx(pos.x, pos.y);
+                occupied.insert(idx);
+                if blocks.is_some() {
+                    blocked.insert(idx);
+                }
+            }
+        }
+        (occupied, blocked)
+    };
+
+    let mut free: Vec<usize> = area
+        .iter()
+        .copied()
+        .filter(|idx| map.tiles[*idx] == TileType::Floor && !occupied.contains(idx))
+        .collect();
+
+    let spawns: Vec<(String, usize)> = {
+        let raws = ecs.fetch::<raws::RawMaster>();
+        let table = raws.spawn_table(depth);
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let rolls = rng.roll_dice(1, MAX_SPAWNS + 3) + (depth - 1) / 2 - 3;
+
+        let mut spawns = Vec::new();
+        for _ in 0..rolls {
+            if free.is_empty() {
+                break;
+            }
+            let name = match table.roll(&mut rng) {
+                Some(name) => name.to_string(),
+                None => break,
+            };
+            let idx = free.swap_remove(rng.range(0, free.len()));
+            // furniture that never moves must not be able to wall off part of the level
+            let blocks_for_good = raws.prop(&name).is_some_and(|p| p.blocks_tile);
+            if blocks_for_good && !surrounded_by_open_floor(map, idx, &blocked) {
+                continue;
+            }
+            if blocks_for_good || raws.monster(&name).is_some() {
+                blocked.insert(idx);
+            }
+            occupied.insert(idx);
+            spawns.push((name, idx));
+        }
+        spawns
+    };
+
+    for (name, idx) in spawns.iter() {
+        let (x, y) = map.idx_xy(*idx);
+        raws::spawn_named_entity(ecs, name, x, y);
+    }
+}
+
+/// True if all eight neighbours are floor with nothing blocking them,
+/// so walkers can always step around whatever is put on `idx`
+fn surrounded_by_open_floor(map: &Map, idx: usize, blocked: &HashSet<usize>) -> bool {
+    let (x, y) = map.idx_xy(idx);
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if (dx, dy) == (0, 0) {
+                continue;
+            }
+            if !map.in_bounds(Point::new(x + dx, y + dy)) {
+                return false;
+            }
+            let neighbour = map.xy_idx(x + dx, y + dy);
+            if map.tiles[neighbour] != TileType::Floor || blocked.contains(&neighbour) {
+                return false;
+            }
+        }
+    }
+    true
+}